@@ -0,0 +1,30 @@
+//! Records the git commit the crate is built from, and whether the working tree had changes, so
+//! every output can say which code wrote it. Builds outside a git checkout record neither.
+
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() { return None; }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+fn main() {
+    // The commit moves with HEAD and the branch it points to, and the dirty flag with the index
+    // and the sources
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    if let Some(branch) = git(&["symbolic-ref", "-q", "HEAD"]) {
+        println!("cargo:rerun-if-changed=.git/{}", branch);
+    }
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+
+    let commit = git(&["rev-parse", "HEAD"]).unwrap_or_default();
+    let dirty = match git(&["status", "--porcelain", "--untracked-files=no"]) {
+        Some(status) if !commit.is_empty() => if status.is_empty() { "false" } else { "true" },
+        _ => "",
+    };
+    println!("cargo:rustc-env=QUASING_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=QUASING_GIT_DIRTY={}", dirty);
+}
@@ -1,7 +1,7 @@
 import matplotlib.pyplot as plt
 import numpy as np
 import os
-from util import load, get_stats_steep
+from util import OUTPUT_DIR, output_name, find_run, load, get_stats_steep
 
 WINDOW = (0.25, 0.55)
 plt.style.use("root")
@@ -25,23 +25,14 @@ def max_left_right(xs, ys):
         res += np.abs(middle(s[argmax-1], s[argmax], s[argmax+1]))
     return res / 2
 
-def get_phase(start_name):
-    etas = []
-    temp_criticals = []
-    for f in os.listdir("../data/output/"):
-        if f.startswith(start_name):
-            eta = float(f[len(start_name):-4])
-            betas, mag, sus = load(f)
-            temp = max(0,get_stats_steep(betas, mag))
-            etas.append(eta)
-            temp_criticals.append(temp)
-    etas = np.array(etas)
-    temp_criticals = np.array(temp_criticals)
-    argsort = np.argsort(etas)
-    return etas[argsort], temp_criticals[argsort]
+def get_phase(name):
+    # Tc against eta, as tabulated by `quasing-model phase`
+    table = np.genfromtxt(f"{OUTPUT_DIR}/{name}-phase.csv", delimiter=',', names=True)
+    table = table[np.argsort(table["eta"])]
+    return table["eta"], np.maximum(0, table["tc"])
 
 def plot(ax, name, label, color, marker):
-    etas, transitions = get_phase(f"{name}-")
+    etas, transitions = get_phase(name)
 
     if name in ["rect-ising", "rect-xy"]:
         print(name, max_left_right(etas, transitions))
@@ -55,24 +46,13 @@ def plot(ax, name, label, color, marker):
     ax.set_xlim(-1,1)
     ax.set_ylim(0,None)
 
-betas, mag, sus = load("ising-square.dat")
-print("Square Ising", get_stats_steep(betas, mag))
-betas, mag, sus = load("xy-square.dat")
-print("Square XY", get_stats_steep(betas, mag))
-betas, mag, sus = load("heisenberg-square.dat")
-print("Square Heisenberg", get_stats_steep(betas, mag))
-betas, mag, sus = load("ising-penrose.dat")
-print("Penrose Ising", get_stats_steep(betas, mag))
-betas, mag, sus = load("xy-penrose.dat")
-print("Penrose XY", get_stats_steep(betas, mag))
-betas, mag, sus = load("heisenberg-penrose.dat")
-print("Penrose Heisenberg", get_stats_steep(betas, mag))
-betas, mag, sus = load("einstein-ising--0.32999998.dat")
-print("Einstein Ising", get_stats_steep(betas, mag))
-betas, mag, sus = load("einstein-xy--0.32999998.dat")
-print("Einstein XY", get_stats_steep(betas, mag))
-betas, mag, sus = load("einstein-heisenberg--0.32999998.dat")
-print("Einstein Heisenberg", get_stats_steep(betas, mag))
+for label, lattice in [("Square", "rect-128"), ("Penrose", "penrose-9")]:
+    for spin in ["ising", "xy", "heisenberg"]:
+        betas, mag, sus = load(output_name(lattice, spin))
+        print(label, spin, get_stats_steep(betas, mag))
+for spin in ["ising", "xy", "heisenberg"]:
+    betas, mag, sus = load(find_run("einstein-7k", spin, eta=-0.33))
+    print("Einstein", spin, get_stats_steep(betas, mag))
 print()
 
 
@@ -1,21 +1,21 @@
 import matplotlib.pyplot as plt
 import numpy as np
-from util import load, get_stats_steep
+from util import output_name, find_run, load, get_stats_steep
 plt.style.use("root")
 
 def display_crystal(ising_name, xy_name, heisenberg_name, save_name):
     fig, (ax_mag, ax_sus) = plt.subplots(figsize=(9, 11), nrows=2, sharex=True)
     lines = [
-        (f"{ising_name}.dat", "maroon", "mediumblue", "o"),
-        (f"{xy_name}.dat", "crimson", "dodgerblue", "^"),
-        (f"{heisenberg_name}.dat", "salmon", "cyan", "d"),
+        (ising_name, "maroon", "mediumblue", "o"),
+        (xy_name, "crimson", "dodgerblue", "^"),
+        (heisenberg_name, "salmon", "cyan", "d"),
     ]
     t_crits = []
-    for filename, red, blue, marker in lines:
-        if filename[:4] == "None": continue
-        betas, mag, sus = load(filename)
+    for name, red, blue, marker in lines:
+        if name is None: continue
+        betas, mag, sus = load(name)
         t_crit = get_stats_steep(betas, mag)
-        print(filename, t_crit)
+        print(name, t_crit)
         t_crits.append(t_crit)
         ax_sus.axvline(t_crit, linestyle="dotted", linewidth=1, color='k', zorder=-2)
         ax_mag.axvline(t_crit, linestyle="dotted", linewidth=1, color='k', zorder=-2)
@@ -41,7 +41,7 @@ def display_crystal(ising_name, xy_name, heisenberg_name, save_name):
     fig.savefig(f"../figs/{save_name}.png", bbox_inches="tight")
     fig.savefig(f"../figs/{save_name}.pdf", bbox_inches="tight")
 
-display_crystal("tim-square", None, None, "tim")
-display_crystal("rect-ising-0.33000004", None, None, "test")
-display_crystal("ising-square", "xy-square", "heisenberg-square", "square")
-display_crystal("ising-penrose", "xy-penrose", "heisenberg-square", "penrose")
\ No newline at end of file
+display_crystal(output_name("rect-128", "tim"), None, None, "tim")
+display_crystal(find_run("rect-128", "ising", eta=0.33), None, None, "test")
+display_crystal(*[output_name("rect-128", spin) for spin in ["ising", "xy", "heisenberg"]], "square")
+display_crystal(*[output_name("penrose-9", spin) for spin in ["ising", "xy", "heisenberg"]], "penrose")
\ No newline at end of file
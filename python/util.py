@@ -1,19 +1,42 @@
+import glob
+import json
+import os
 import numpy as np
 
-def load(filename):
-    with open(f"../data/output/{filename}", 'r') as f:
-        betas = f.readline().split(',')[:-1]
-        betas = np.array([float(b) for b in betas])
-        mag = f.readline().split(',')[:-1]
-        mag = np.array([float(s) for s in mag])
-        sus = f.readline().split(',')[:-1]
-        sus = np.array([float(s) for s in sus])
-        argsort = np.argsort(betas)
-        betas = betas[argsort]
-        mag = mag[argsort]
-        sus = sus[argsort]
-        return betas, mag, sus
-    
+OUTPUT_DIR = "../data/output"
+
+def output_name(lattice, spin):
+    """Name quasing-model gives a run without --name, e.g. output_name("rect-128", "ising")"""
+    return f"{lattice}-{spin}"
+
+def load_run(name):
+    """Metadata of a run, from its JSON sidecar"""
+    with open(f"{OUTPUT_DIR}/{name}.json") as f:
+        return json.load(f)
+
+def find_run(lattice, spin, **parameters):
+    """Name of the run on `lattice` with `spin` whose parameters match, e.g. eta=-0.33, as read
+    from the JSON sidecars. Parameters match to 1e-4, so they need not be written exactly."""
+    matches = []
+    for filename in sorted(glob.glob(f"{OUTPUT_DIR}/*.json")):
+        run = load_run(os.path.basename(filename)[:-len(".json")])
+        if run.get("lattice") != lattice or run.get("spin") != spin: continue
+        values = run.get("parameters", {})
+        if all(key in values and abs(values[key] - value) < 1e-4 for key, value in parameters.items()):
+            matches.append(os.path.basename(filename)[:-len(".json")])
+    if len(matches) != 1:
+        raise LookupError(f"{len(matches)} runs of {spin} on {lattice} with {parameters}: {matches}")
+    return matches[0]
+
+def load_table(name):
+    """All columns of the output CSV of run `name`, sorted by beta, as a structured array"""
+    table = np.genfromtxt(f"{OUTPUT_DIR}/{name}.csv", delimiter=',', names=True)
+    return table[np.argsort(table["beta"])]
+
+def load(name):
+    table = load_table(name)
+    return table["beta"], table["magnetization"], table["susceptibility"]
+
 def get_stats_steep(betas, mag):
     max_slope = None
     max_slope_temp = None
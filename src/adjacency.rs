@@ -0,0 +1,171 @@
+use std::io::Read;
+
+use npy::NpyData;
+
+use crate::validate::{validate, Expect, LoadError};
+
+/// Compressed-sparse-row adjacency. The neighbors of site `i` are `targets[offsets[i]..offsets[i+1]]`,
+/// and each bond carries a kind that indexes into a coupling table, so that one adjacency can be
+/// shared between lattices with different couplings.
+pub struct Adjacency {
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    kinds: Vec<usize>,
+}
+
+impl Adjacency {
+    /// Build the adjacency from a list of `(site, site, kind)` bonds, each listed once, in O(N+E)
+    pub fn from_bonds(num_sites: usize, bonds: &[(usize, usize, usize)]) -> Self {
+        let mut offsets = vec![0; num_sites + 1];
+        for (a, b, _) in bonds {
+            offsets[*a + 1] += 1;
+            offsets[*b + 1] += 1;
+        }
+        for i in 0..num_sites {
+            offsets[i + 1] += offsets[i];
+        }
+
+        let mut fill = offsets.clone();
+        let mut targets = vec![0; 2 * bonds.len()];
+        let mut kinds = vec![0; 2 * bonds.len()];
+        for (a, b, kind) in bonds {
+            targets[fill[*a]] = *b;
+            kinds[fill[*a]] = *kind;
+            fill[*a] += 1;
+            targets[fill[*b]] = *a;
+            kinds[fill[*b]] = *kind;
+            fill[*b] += 1;
+        }
+        Self { offsets, targets, kinds }
+    }
+
+    /// Read the bond pairs stored in `.npy` files, where the bonds of `filenames[k]` have kind `k`.
+    /// The number of sites is one more than the largest index. The result must be a connected graph
+    /// with no self-loops or repeated bonds.
+    pub fn from_npy(filenames: &[String]) -> Result<Self, LoadError> {
+        let mut bonds = Vec::new();
+        for (kind, filename) in filenames.iter().enumerate() {
+            let mut buf = vec![];
+            std::fs::File::open(filename).and_then(|mut f| f.read_to_end(&mut buf))
+                .map_err(|source| LoadError::Io { file: filename.clone(), source })?;
+            let parse_error = |message: String| LoadError::Parse { file: filename.clone(), line: None, message };
+            let indices = NpyData::<i64>::from_bytes(&buf)
+                .map_err(|e| parse_error(format!("not an i64 .npy array: {}", e)))?.to_vec();
+            if indices.len() % 2 != 0 {
+                return Err(parse_error(format!("{} indices cannot be split into pairs", indices.len())));
+            }
+            for (bond, pair) in indices.chunks(2).enumerate() {
+                if pair[0] < 0 || pair[1] < 0 {
+                    return Err(parse_error(format!("bond {} has negative index ({}, {})", bond, pair[0], pair[1])));
+                }
+                bonds.push((pair[0] as usize, pair[1] as usize, kind));
+            }
+        }
+        let num_sites = bonds.iter().map(|(a, b, _)| a.max(b) + 1).max().unwrap_or(0);
+        let adjacency = Self::from_bonds(num_sites, &bonds);
+
+        let problems = validate(num_sites, &adjacency.neighbor_fn(&vec![1.; filenames.len()]), &Expect::connected());
+        if !problems.is_empty() {
+            return Err(LoadError::Invalid { file: filenames.join(", "), problems });
+        }
+        Ok(adjacency)
+    }
+
+    pub fn num_sites(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Neighbors of `site` and the kinds of the bonds to them
+    pub fn neighbors(&self, site: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let range = self.offsets[site]..self.offsets[site + 1];
+        self.targets[range.clone()].iter().copied().zip(self.kinds[range].iter().copied())
+    }
+
+    /// Neighbor function where a bond of kind `k` has coupling `couplings[k]`
+    pub fn neighbor_fn<'a>(&'a self, couplings: &'a [f32]) -> impl Fn(usize) -> Vec<(usize, f32)> + 'a {
+        move |site: usize| {
+            self.neighbors(site).map(|(neighbor, kind)| (neighbor, couplings[kind])).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::write_i64;
+    use crate::validate::Problem;
+
+    /// Square 0-1-2-3 with a diagonal 0-2 of kind 1, and site 4 hanging off site 3
+    const BONDS: [(usize, usize, usize); 6] = [(0, 1, 0), (1, 2, 0), (2, 3, 0), (3, 0, 0), (0, 2, 1), (3, 4, 0)];
+
+    #[test]
+    fn csr_has_the_degree_of_every_site() {
+        let adjacency = Adjacency::from_bonds(6, &BONDS);
+        assert_eq!(adjacency.num_sites(), 6);
+        let degrees = (0..6).map(|site| adjacency.neighbors(site).count()).collect::<Vec<_>>();
+        assert_eq!(degrees, vec![3, 2, 3, 3, 1, 0]);
+    }
+
+    #[test]
+    fn every_bond_is_listed_from_both_ends_with_its_kind() {
+        let adjacency = Adjacency::from_bonds(6, &BONDS);
+        for (a, b, kind) in BONDS {
+            assert!(adjacency.neighbors(a).any(|n| n == (b, kind)), "{} misses {}", a, b);
+            assert!(adjacency.neighbors(b).any(|n| n == (a, kind)), "{} misses {}", b, a);
+        }
+        let mut neighbors = adjacency.neighbors(0).collect::<Vec<_>>();
+        neighbors.sort();
+        assert_eq!(neighbors, vec![(1, 0), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn neighbor_fn_gives_each_kind_its_coupling() {
+        let adjacency = Adjacency::from_bonds(6, &BONDS);
+        let couplings = [1., -0.5];
+        let func = adjacency.neighbor_fn(&couplings);
+        let mut neighbors = func(2);
+        neighbors.sort_by_key(|n| n.0);
+        assert_eq!(neighbors, vec![(0, -0.5), (1, 1.), (3, 1.)]);
+    }
+
+    /// Writes the flattened index pairs `indices` to a temporary `.npy` file and returns its name
+    fn write_bonds(name: &str, indices: &[i64]) -> String {
+        let filename = std::env::temp_dir().join(format!("quasing-{}-{}.npy", name, std::process::id()));
+        let filename = filename.to_str().unwrap().to_owned();
+        write_i64(&filename, &[indices.len()], indices).unwrap();
+        filename
+    }
+
+    fn load(name: &str, indices: &[i64]) -> Result<Adjacency, LoadError> {
+        let file = write_bonds(name, indices);
+        let result = Adjacency::from_npy(std::slice::from_ref(&file));
+        std::fs::remove_file(&file).unwrap();
+        result
+    }
+
+    #[test]
+    fn npy_files_give_one_kind_each() {
+        let files = [write_bonds("edges", &[0, 1, 1, 2, 2, 3, 3, 0]), write_bonds("diagonals", &[0, 2])];
+        let adjacency = Adjacency::from_npy(&files).unwrap();
+        for file in &files {
+            std::fs::remove_file(file).unwrap();
+        }
+        assert_eq!(adjacency.num_sites(), 4);
+        assert!(adjacency.neighbors(2).any(|n| n == (0, 1)));
+        assert_eq!(adjacency.neighbors(1).count(), 2);
+    }
+
+    #[test]
+    fn npy_graphs_must_be_simple_and_connected() {
+        let problems = |result: Result<Adjacency, LoadError>| match result {
+            Err(LoadError::Invalid { problems, .. }) => problems,
+            _ => panic!("broken graph was accepted"),
+        };
+        assert_eq!(problems(load("disconnected", &[0, 1, 2, 3])),
+                   vec![Problem::Disconnected { components: 2, largest: 2 }]);
+        assert!(problems(load("self-loop", &[0, 1, 1, 1])).contains(&Problem::SelfLoop { site: 1 }));
+        assert!(problems(load("repeated", &[0, 1, 1, 0])).iter().any(|p| matches!(p, Problem::DuplicateBond { .. })));
+        assert!(matches!(load("negative", &[0, 1, 1, -1]), Err(LoadError::Parse { .. })));
+        assert!(matches!(load("odd", &[0, 1, 1]), Err(LoadError::Parse { .. })));
+    }
+}
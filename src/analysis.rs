@@ -0,0 +1,411 @@
+//! Critical temperatures from sweep output
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::lattice::Report;
+use crate::output::Data;
+
+/// Resamples used for bootstrap uncertainties
+pub const NUM_RESAMPLES: usize = 200;
+
+/// Observable whose peak in temperature marks the transition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Peak {
+    Susceptibility,
+    SpecificHeat,
+    /// Steepest descent of the Binder cumulant, the peak of `-dU4/dT`
+    Binder,
+}
+
+impl Peak {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "susceptibility" => Peak::Susceptibility,
+            "specific-heat" => Peak::SpecificHeat,
+            "binder" => Peak::Binder,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Peak::Susceptibility => "susceptibility",
+            Peak::SpecificHeat => "specific-heat",
+            Peak::Binder => "binder",
+        }
+    }
+}
+
+/// A temperature with its uncertainty
+#[derive(Clone, Copy, Debug)]
+pub struct Estimate {
+    pub temperature: f32,
+    pub error: f32,
+}
+
+/// Peak located on a temperature grid
+pub struct Located {
+    pub estimate: Estimate,
+    /// Temperatures of the grid points either side of the highest one
+    pub bracket: (f32, f32),
+    /// Whether the highest point is at the edge of the grid, so the peak may lie outside it
+    pub at_edge: bool,
+}
+
+/// `(temperature, value, error)` of one observable of `data`, chosen by `observable`, sorted by
+/// temperature
+pub fn column(data: &Data, observable: impl Fn(&Report) -> (f32, f32)) -> Vec<(f32, f32, f32)> {
+    let mut points = data.betas.iter().zip(&data.reports).map(|(beta, r)| {
+        let (value, error) = observable(r);
+        (1. / beta, value, error)
+    }).collect::<Vec<_>>();
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    points
+}
+
+/// Slope of the piecewise linear `points` between each pair, at their midpoints
+pub fn slopes(points: &[(f32, f32, f32)]) -> Vec<(f32, f32, f32)> {
+    points.windows(2).filter(|w| w[1].0 > w[0].0).map(|w| {
+        let dt = w[1].0 - w[0].0;
+        ((w[0].0 + w[1].0) / 2., (w[1].1 - w[0].1) / dt, w[0].2.hypot(w[1].2) / dt)
+    }).collect()
+}
+
+/// `(temperature, value, error)` points of `data` whose maximum is the `peak`, sorted by
+/// temperature. Binder slopes sit at the midpoints between temperatures.
+pub fn curve(data: &Data, peak: Peak) -> Vec<(f32, f32, f32)> {
+    match peak {
+        Peak::Susceptibility => column(data, |r| (r.susceptibility, r.susceptibility_error)),
+        Peak::SpecificHeat => column(data, |r| (r.specific_heat, r.specific_heat_error)),
+        Peak::Binder => slopes(&column(data, |r| (r.binder, r.binder_error))).into_iter()
+            .map(|(t, slope, error)| (t, -slope, error)).collect(),
+    }
+}
+
+/// Vertex of the parabola through three points, kept within their range
+fn vertex(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    let num = a.0 * a.0 * (b.1 - c.1) + b.0 * b.0 * (c.1 - a.1) + c.0 * c.0 * (a.1 - b.1);
+    let denom = a.0 * (b.1 - c.1) + b.0 * (c.1 - a.1) + c.0 * (a.1 - b.1);
+    let x = 0.5 * num / denom;
+    if x.is_finite() { x.clamp(a.0, c.0) } else { b.0 }
+}
+
+/// Index of the highest of `values`, ignoring NaN
+fn argmax(values: impl Iterator<Item = f32>) -> Option<usize> {
+    values.enumerate().filter(|(_, y)| !y.is_nan()).max_by(|a, b| a.1.total_cmp(&b.1)).map(|(i, _)| i)
+}
+
+/// Index of the highest point of `points` and the vertex of the parabola through it and its
+/// neighbors
+fn peak_vertex(points: &[(f32, f32)]) -> Option<(usize, f32)> {
+    let highest = argmax(points.iter().map(|p| p.1))?;
+    let k = highest.clamp(1, points.len() - 2);
+    Some((highest, vertex(points[k - 1], points[k], points[k + 1])))
+}
+
+/// Standard deviation of `samples`
+pub fn spread(samples: &[f32]) -> f32 {
+    let n = samples.len() as f64;
+    let mean = samples.iter().map(|&x| x as f64).sum::<f64>() / n;
+    let variance = samples.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / n;
+    variance.sqrt() as f32
+}
+
+/// `(x, y)` points with every `y` redrawn from a normal distribution of its error
+pub fn redraw(points: &[(f32, f32, f32)], rng: &mut ChaCha8Rng) -> Vec<(f32, f32)> {
+    points.iter().map(|&(x, y, e)| {
+        (x, Normal::new(y, if e.is_finite() { e } else { 0. }).map_or(y, |d| d.sample(rng)))
+    }).collect()
+}
+
+/// Estimate of `estimator` on the central values of `curves`, with the spread of its values over
+/// `NUM_RESAMPLES` copies of the curves redrawn within their errors. This is a parametric bootstrap,
+/// since sweeps keep only the jackknife error of each point.
+pub fn bootstrap(curves: &[Vec<(f32, f32, f32)>], estimator: impl Fn(&[Vec<(f32, f32)>]) -> Option<f32>) -> Option<Estimate> {
+    let central = curves.iter().map(|c| c.iter().map(|&(x, y, _)| (x, y)).collect()).collect::<Vec<_>>();
+    let temperature = estimator(&central)?;
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let resamples = (0..NUM_RESAMPLES).filter_map(|_| {
+        estimator(&curves.iter().map(|c| redraw(c, &mut rng)).collect::<Vec<_>>())
+    }).collect::<Vec<_>>();
+    Some(Estimate { temperature, error: spread(&resamples) })
+}
+
+/// Locates the maximum of a `curve` by a parabola through the highest point and its neighbors. The
+/// uncertainty is bootstrapped, so it includes redrawn curves that peak at a different point.
+pub fn locate(curve: &[(f32, f32, f32)]) -> Option<Located> {
+    if curve.len() < 3 {
+        return None;
+    }
+    let points = curve.iter().map(|&(x, y, _)| (x, y)).collect::<Vec<_>>();
+    let (highest, _) = peak_vertex(&points)?;
+    let k = highest.clamp(1, curve.len() - 2);
+    let estimate = bootstrap(&[curve.to_vec()], |curves| peak_vertex(&curves[0]).map(|(_, x)| x))?;
+    Some(Located {
+        estimate,
+        bracket: (curve[k - 1].0, curve[k + 1].0),
+        at_edge: highest == 0 || highest == curve.len() - 1,
+    })
+}
+
+/// Where the steepest drop of the magnetization, extended as a line, crosses one half. This is the
+/// estimate of `python/util.py`.
+pub fn steepest_descent(data: &Data) -> Option<Estimate> {
+    bootstrap(&[column(data, |r| (r.magnetization, r.magnetization_error))], |curves| {
+        let (slope, t, m) = curves[0].windows(2).filter(|w| w[1].0 > w[0].0)
+            .map(|w| ((w[1].1 - w[0].1) / (w[1].0 - w[0].0), (w[0].0 + w[1].0) / 2., (w[0].1 + w[1].1) / 2.))
+            .min_by(|a, b| a.0.total_cmp(&b.0))?;
+        (slope < 0.).then(|| t + (0.5 - m) / slope)
+    })
+}
+
+/// Vertex of the least-squares parabola through `points`
+fn fit_parabola(points: &[(f32, f32)]) -> Option<f32> {
+    // Centered on the first point for conditioning
+    let x0 = points.first()?.0 as f64;
+    let mut sums = [0f64; 5];
+    let mut rhs = [0f64; 3];
+    for &(x, y) in points {
+        let x = x as f64 - x0;
+        for (p, s) in sums.iter_mut().enumerate() {
+            *s += x.powi(p as i32);
+        }
+        for (p, r) in rhs.iter_mut().enumerate() {
+            *r += x.powi(p as i32) * y as f64;
+        }
+    }
+    // Normal equations for y = a + b x + c x^2, by Cramer's rule
+    let m = [[sums[0], sums[1], sums[2]], [sums[1], sums[2], sums[3]], [sums[2], sums[3], sums[4]]];
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(m);
+    let with_column = |j: usize| {
+        let mut m = m;
+        for (row, r) in m.iter_mut().zip(rhs) {
+            row[j] = r;
+        }
+        det(m) / d
+    };
+    let (b, c) = (with_column(1), with_column(2));
+    let x = x0 - b / (2. * c);
+    (d != 0. && c != 0. && x.is_finite()).then_some(x as f32)
+}
+
+/// Points of a peaked `curve` either side of its highest point down to half its height
+fn upper_half(points: &[(f32, f32)]) -> Option<&[(f32, f32)]> {
+    let highest = argmax(points.iter().map(|p| p.1))?;
+    let half = points[highest].1 / 2.;
+    let start = points[..highest].iter().rposition(|p| p.1 < half).map_or(0, |i| i + 1);
+    let end = points[highest..].iter().position(|p| p.1 < half).map_or(points.len(), |i| highest + i);
+    Some(&points[start..end])
+}
+
+/// Shape fitted to the susceptibility peak
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    /// Parabola through the highest point and its neighbors
+    Quadratic,
+    /// `A / (1 + ((T - Tc) / w)^2)` over the points above half the peak height, fitted as a
+    /// parabola in `1 / χ`
+    Lorentzian,
+}
+
+/// Temperature of the susceptibility maximum
+pub fn susceptibility_peak(data: &Data, fit: Fit) -> Option<Estimate> {
+    let curve = curve(data, Peak::Susceptibility);
+    match fit {
+        Fit::Quadratic => locate(&curve).map(|l| l.estimate),
+        Fit::Lorentzian => bootstrap(&[curve], |curves| {
+            let points = upper_half(&curves[0])?;
+            let (low, high) = (points.first()?.0, points.last()?.0);
+            if points.len() < 3 || points.iter().any(|p| p.1 <= 0.) {
+                return None;
+            }
+            let inverse = points.iter().map(|&(t, y)| (t, 1. / y)).collect::<Vec<_>>();
+            fit_parabola(&inverse).map(|t| t.clamp(low, high))
+        }),
+    }
+}
+
+/// Temperature of the specific heat maximum
+pub fn specific_heat_peak(data: &Data) -> Option<Estimate> {
+    locate(&curve(data, Peak::SpecificHeat)).map(|l| l.estimate)
+}
+
+/// Value of the piecewise linear `points` at `x`, if within their range
+pub fn interpolate(points: &[(f32, f32)], x: f32) -> Option<f32> {
+    let i = points.windows(2).position(|w| w[0].0 <= x && x <= w[1].0)?;
+    let (a, b) = (points[i], points[i + 1]);
+    Some(if b.0 > a.0 { a.1 + (b.1 - a.1) * (x - a.0) / (b.0 - a.0) } else { a.1 })
+}
+
+/// Lowest temperature where the linearly interpolated `a` and `b` cross
+fn crossing(a: &[(f32, f32)], b: &[(f32, f32)]) -> Option<f32> {
+    let mut temps = a.iter().chain(b).map(|p| p.0).collect::<Vec<_>>();
+    temps.sort_by(f32::total_cmp);
+    temps.dedup();
+    let differences = temps.iter().filter_map(|&t| Some((t, interpolate(a, t)? - interpolate(b, t)?))).collect::<Vec<_>>();
+    differences.windows(2).find(|w| w[0].1 == 0. || w[0].1.signum() != w[1].1.signum())
+        .map(|w| if w[0].1 == w[1].1 { w[0].0 } else { w[0].0 - w[0].1 * (w[1].0 - w[0].0) / (w[1].1 - w[0].1) })
+}
+
+/// Mean temperature where the Binder cumulants of successive `sizes`, sweeps of one model on
+/// lattices of increasing size, cross
+pub fn binder_crossing(sizes: &[&Data]) -> Option<Estimate> {
+    let curves = sizes.iter().map(|data| column(data, |r| (r.binder, r.binder_error))).collect::<Vec<_>>();
+    bootstrap(&curves, mean_crossing)
+}
+
+/// Mean temperature where successive `curves` cross
+pub fn mean_crossing(curves: &[Vec<(f32, f32)>]) -> Option<f32> {
+    let crossings = curves.windows(2).filter_map(|pair| crossing(&pair[0], &pair[1])).collect::<Vec<_>>();
+    (!crossings.is_empty()).then(|| crossings.iter().sum::<f32>() / crossings.len() as f32)
+}
+
+/// Single-sweep estimate of Tc
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Estimator {
+    SteepestDescent,
+    SusceptibilityQuadratic,
+    SusceptibilityLorentzian,
+    SpecificHeat,
+}
+
+impl Estimator {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "steepest-descent" => Estimator::SteepestDescent,
+            "susceptibility-quadratic" => Estimator::SusceptibilityQuadratic,
+            "susceptibility-lorentzian" => Estimator::SusceptibilityLorentzian,
+            "specific-heat" => Estimator::SpecificHeat,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Estimator::SteepestDescent => "steepest-descent",
+            Estimator::SusceptibilityQuadratic => "susceptibility-quadratic",
+            Estimator::SusceptibilityLorentzian => "susceptibility-lorentzian",
+            Estimator::SpecificHeat => "specific-heat",
+        }
+    }
+}
+
+/// Every single-sweep estimator of Tc
+pub const ESTIMATORS: [Estimator; 4] = [Estimator::SteepestDescent, Estimator::SusceptibilityQuadratic,
+    Estimator::SusceptibilityLorentzian, Estimator::SpecificHeat];
+
+/// Estimate of Tc from `data` by `estimator`, if the sweep supports it
+pub fn estimate(data: &Data, estimator: Estimator) -> Option<Estimate> {
+    match estimator {
+        Estimator::SteepestDescent => steepest_descent(data),
+        Estimator::SusceptibilityQuadratic => susceptibility_peak(data, Fit::Quadratic),
+        Estimator::SusceptibilityLorentzian => susceptibility_peak(data, Fit::Lorentzian),
+        Estimator::SpecificHeat => specific_heat_peak(data),
+    }
+}
+
+/// Every single-sweep estimate of Tc that `data` supports, by name
+pub fn estimates(data: &Data) -> Vec<(&'static str, Estimate)> {
+    ESTIMATORS.into_iter().filter_map(|estimator| estimate(data, estimator).map(|e| (estimator.name(), e))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sweep at temperatures from 1 to 3 in steps of 0.1, with exact observables from `report`
+    fn sweep(report: impl Fn(f32) -> Report) -> Data {
+        let mut data = Data::default();
+        for i in 0..=20 {
+            let t = 1. + 0.1 * i as f32;
+            data.push(1. / t, report(t));
+        }
+        data
+    }
+
+    fn report(magnetization: f32, susceptibility: f32, specific_heat: f32, binder: f32) -> Report {
+        Report {
+            magnetization, magnetization_error: 0.,
+            susceptibility, susceptibility_error: 0.,
+            energy: f32::NAN, energy_error: f32::NAN,
+            specific_heat, specific_heat_error: 0.,
+            binder, binder_error: 0.,
+            num_measurements: 1000,
+        }
+    }
+
+    /// Peak of height one and half width 0.3 at `tc`
+    fn lorentzian(t: f32, tc: f32) -> f32 {
+        1. / (1. + ((t - tc) / 0.3).powi(2))
+    }
+
+    const TC: f32 = 2.03;
+
+    fn peaked() -> Data {
+        sweep(|t| report(0.5 * (1. - ((t - TC) / 0.2).tanh()), lorentzian(t, TC), lorentzian(t, TC), f32::NAN))
+    }
+
+    fn assert_near(estimate: Option<Estimate>, expected: f32, tolerance: f32) {
+        let estimate = estimate.expect("no estimate");
+        assert!((estimate.temperature - expected).abs() < tolerance, "{} is not within {} of {}", estimate.temperature, tolerance, expected);
+        assert!(estimate.error.abs() < 1e-6, "exact curves have error {}", estimate.error);
+    }
+
+    #[test]
+    fn steepest_descent_finds_the_middle_of_the_drop() {
+        assert_near(steepest_descent(&peaked()), TC, 0.02);
+    }
+
+    #[test]
+    fn quadratic_fit_finds_the_susceptibility_peak() {
+        assert_near(susceptibility_peak(&peaked(), Fit::Quadratic), TC, 0.02);
+    }
+
+    #[test]
+    fn lorentzian_fit_recovers_a_lorentzian_peak() {
+        assert_near(susceptibility_peak(&peaked(), Fit::Lorentzian), TC, 1e-3);
+    }
+
+    #[test]
+    fn specific_heat_peak_is_found() {
+        assert_near(specific_heat_peak(&peaked()), TC, 0.02);
+    }
+
+    #[test]
+    fn every_estimator_is_reachable_by_name() {
+        let data = peaked();
+        assert_eq!(estimates(&data).len(), ESTIMATORS.len());
+        for estimator in ESTIMATORS {
+            assert_eq!(Estimator::from_name(estimator.name()), Some(estimator));
+        }
+        assert_eq!(Estimator::from_name("binder"), None);
+    }
+
+    #[test]
+    fn peak_at_the_edge_is_flagged() {
+        let data = sweep(|t| report(f32::NAN, lorentzian(t, 0.5), f32::NAN, f32::NAN));
+        let located = locate(&curve(&data, Peak::Susceptibility)).unwrap();
+        assert!(located.at_edge);
+        assert_eq!(located.bracket, (1., 1.2));
+    }
+
+    #[test]
+    fn binder_cumulants_cross_at_tc() {
+        let small = sweep(|t| report(f32::NAN, f32::NAN, f32::NAN, 0.6 - 0.1 * (t - 2.27)));
+        let large = sweep(|t| report(f32::NAN, f32::NAN, f32::NAN, 0.6 - 0.3 * (t - 2.27)));
+        assert_near(binder_crossing(&[&small, &large]), 2.27, 1e-4);
+    }
+
+    #[test]
+    fn parallel_binder_cumulants_do_not_cross() {
+        let small = sweep(|t| report(f32::NAN, f32::NAN, f32::NAN, 0.6 - 0.1 * t));
+        let large = sweep(|t| report(f32::NAN, f32::NAN, f32::NAN, 0.5 - 0.1 * t));
+        assert!(binder_crossing(&[&small, &large]).is_none());
+    }
+}
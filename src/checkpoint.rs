@@ -0,0 +1,224 @@
+//! Checkpoints of a temperature sweep, so that a killed sweep can continue exactly where it
+//! stopped. The format is plain text:
+//!
+//! ```text
+//! quasing-checkpoint 1
+//! sweep <free text identifying the sweep>
+//! rng <seed as hex> <stream> <word position>
+//! completed <number of finished betas>
+//! <output row>                        (one line per finished beta, as in the output CSV)
+//! current none | <beta> <burned in: 0 or 1> <number of measurements>
+//! <magnetization> <energy>            (one line per measurement)
+//! sites <number of sites>
+//! <value> ... <value>                 (one line per site, from `Sampler::configuration`)
+//! ```
+//!
+//! Version 1 had `spins <number of sites> <number of components>` in place of the `sites` line,
+//! which is still read.
+
+use std::{fs::File, io::{Write, BufRead, BufReader}};
+
+use rand_chacha::ChaCha8Rng;
+use rand::SeedableRng;
+
+use crate::lattice::RunState;
+use crate::output::{row, parse_row, Data};
+use crate::sampler::Sampler;
+use crate::validate::LoadError;
+
+pub const FORMAT_VERSION: usize = 2;
+
+/// Everything needed to continue a sweep
+pub struct Checkpoint {
+    /// Identifies the sweep, so that a checkpoint is not resumed into a different one
+    pub sweep: String,
+    pub rng: ChaCha8Rng,
+    /// Betas finished so far
+    pub completed: Data,
+    /// Beta in progress, if any
+    pub current: Option<RunState>,
+    /// State of every site, as from `Sampler::configuration`
+    pub configuration: Vec<Vec<f32>>,
+}
+
+/// Write a checkpoint of `lattice` in the middle of a sweep. The file is replaced atomically, so
+/// a crash while writing leaves the previous checkpoint intact.
+pub fn save<L: Sampler>(filename: &str, sweep: &str, lattice: &L, completed: &Data, current: Option<&RunState>) -> Result<(), LoadError> {
+    let temp = format!("{}.tmp", filename);
+    write(&temp, sweep, lattice, completed, current).map_err(|source| LoadError::Io { file: temp.clone(), source })?;
+    std::fs::rename(&temp, filename).map_err(|source| LoadError::Io { file: filename.to_owned(), source })
+}
+
+fn write<L: Sampler>(filename: &str, sweep: &str, lattice: &L, completed: &Data, current: Option<&RunState>) -> std::io::Result<()> {
+    let mut f = File::create(filename)?;
+    writeln!(f, "quasing-checkpoint {}", FORMAT_VERSION)?;
+    writeln!(f, "sweep {}", sweep.replace('\n', " "))?;
+    let rng = lattice.rng();
+    let seed = rng.get_seed().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    writeln!(f, "rng {} {} {}", seed, rng.get_stream(), rng.get_word_pos())?;
+    writeln!(f, "completed {}", completed.betas.len())?;
+    for (beta, report) in completed.betas.iter().zip(&completed.reports) {
+        writeln!(f, "{}", row(*beta, report))?;
+    }
+    match current {
+        None => writeln!(f, "current none")?,
+        Some(state) => {
+            writeln!(f, "current {} {} {}", state.beta, state.burned_in as usize, state.magnetizations.len())?;
+            for (m, e) in state.magnetizations.iter().zip(&state.energies) {
+                writeln!(f, "{} {}", m, e)?;
+            }
+        }
+    }
+    writeln!(f, "sites {}", lattice.num_sites())?;
+    for values in lattice.configuration() {
+        let line = values.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ");
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+pub fn load(filename: &str) -> Result<Checkpoint, LoadError> {
+    let file = File::open(filename).map_err(|source| LoadError::Io { file: filename.to_owned(), source })?;
+    let mut lines = BufReader::new(file).lines().enumerate();
+    let error = |line: usize, message: String| {
+        LoadError::Parse { file: filename.to_owned(), line: Some(line + 1), message }
+    };
+    let mut next = || -> Result<(usize, String), LoadError> {
+        match lines.next() {
+            Some((i, Ok(line))) => Ok((i, line)),
+            Some((_, Err(source))) => Err(LoadError::Io { file: filename.to_owned(), source }),
+            None => Err(LoadError::Parse { file: filename.to_owned(), line: None, message: "unexpected end of file".to_owned() }),
+        }
+    };
+    let keyword = |(i, line): &(usize, String), key: &str| -> Result<Vec<String>, LoadError> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some(key) {
+            return Err(error(*i, format!("expected {:?}", key)));
+        }
+        Ok(words.map(|w| w.to_owned()).collect())
+    };
+    fn parse<T: std::str::FromStr>(word: Option<&String>) -> Result<T, String> {
+        let word = word.ok_or("missing value")?;
+        word.parse().map_err(|_| format!("could not parse {:?}", word))
+    }
+
+    let header = next()?;
+    let version: usize = parse(keyword(&header, "quasing-checkpoint")?.first()).map_err(|m| error(header.0, m))?;
+    if version > FORMAT_VERSION {
+        return Err(error(header.0, format!("version {} is newer than this code", version)));
+    }
+    let sweep = keyword(&next()?, "sweep")?.join(" ");
+
+    let rng_line = next()?;
+    let words = keyword(&rng_line, "rng")?;
+    let seed_hex = words.first().filter(|w| w.len() == 64).ok_or_else(|| error(rng_line.0, "expected a 32-byte seed".to_owned()))?;
+    let mut seed = [0u8; 32];
+    for (i, byte) in seed.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&seed_hex[2 * i..2 * i + 2], 16).map_err(|_| error(rng_line.0, "could not parse seed".to_owned()))?;
+    }
+    let mut rng = ChaCha8Rng::from_seed(seed);
+    rng.set_stream(parse(words.get(1)).map_err(|m| error(rng_line.0, m))?);
+    rng.set_word_pos(parse(words.get(2)).map_err(|m| error(rng_line.0, m))?);
+
+    let completed_line = next()?;
+    let num_completed: usize = parse(keyword(&completed_line, "completed")?.first()).map_err(|m| error(completed_line.0, m))?;
+    let mut completed = Data::default();
+    for _ in 0..num_completed {
+        let (i, line) = next()?;
+        let (beta, report) = parse_row(&line).ok_or_else(|| error(i, "could not parse output row".to_owned()))?;
+        completed.push(beta, report);
+    }
+
+    let current_line = next()?;
+    let words = keyword(&current_line, "current")?;
+    let current = if words.first().map(|w| w.as_str()) == Some("none") {
+        None
+    } else {
+        let beta = parse(words.first()).map_err(|m| error(current_line.0, m))?;
+        let burned_in = parse::<usize>(words.get(1)).map_err(|m| error(current_line.0, m))? != 0;
+        let count: usize = parse(words.get(2)).map_err(|m| error(current_line.0, m))?;
+        let mut state = RunState::new(beta);
+        state.burned_in = burned_in;
+        for _ in 0..count {
+            let (i, line) = next()?;
+            let values = line.split_whitespace().map(|w| w.parse::<f32>()).collect::<Result<Vec<_>, _>>()
+                .map_err(|_| error(i, "could not parse measurement".to_owned()))?;
+            if values.len() != 2 {
+                return Err(error(i, "expected a magnetization and an energy".to_owned()));
+            }
+            state.magnetizations.push(values[0]);
+            state.energies.push(values[1]);
+        }
+        Some(state)
+    };
+
+    let sites_line = next()?;
+    let words = match version {
+        1 => keyword(&sites_line, "spins")?,
+        _ => keyword(&sites_line, "sites")?,
+    };
+    let num_sites: usize = parse(words.first()).map_err(|m| error(sites_line.0, m))?;
+    let mut configuration = Vec::with_capacity(num_sites);
+    for _ in 0..num_sites {
+        let (i, line) = next()?;
+        let values = line.split_whitespace().map(|w| w.parse::<f32>()).collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error(i, "could not parse site".to_owned()))?;
+        configuration.push(values);
+    }
+
+    Ok(Checkpoint { sweep, rng, completed, current, configuration })
+}
+
+impl Checkpoint {
+    /// Put the random number generator and configuration back into `lattice`, returning the
+    /// finished betas and the one in progress
+    pub fn restore<L: Sampler>(self, lattice: &mut L) -> (Data, Option<RunState>) {
+        assert_eq!(self.configuration.len(), lattice.num_sites(), "Checkpoint has a different number of sites from the lattice");
+        lattice.set_rng(self.rng);
+        lattice.set_configuration(&self.configuration);
+        (self.completed, self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::funcs::rect_fn;
+    use crate::lattice::Lattice;
+    use crate::output::row;
+    use crate::spin::XY;
+
+    #[test]
+    fn restored_sweep_continues_exactly() {
+        let mut lattice = Lattice::<XY>::new(36, &rect_fn(6, 1., 1.));
+        lattice.seed(7);
+        lattice.zero();
+        let mut completed = Data::default();
+        completed.push(0.5, lattice.run(0.5, 200, (usize::MAX, 2.)).unwrap());
+        lattice.step(0.8, 20);
+        let current = RunState { beta: 0.8, burned_in: true, magnetizations: vec![0.25, 0.5], energies: vec![-1., -1.5] };
+
+        let filename = std::env::temp_dir().join(format!("quasing-checkpoint-{}", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        save(filename, "test sweep", &lattice, &completed, Some(&current)).unwrap();
+        let loaded = load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+
+        assert_eq!(loaded.sweep, "test sweep");
+        assert_eq!(&loaded.rng, lattice.rng());
+        assert_eq!(loaded.completed.betas, completed.betas);
+        assert_eq!(row(loaded.completed.betas[0], &loaded.completed.reports[0]), row(completed.betas[0], &completed.reports[0]));
+        assert_eq!(loaded.configuration, lattice.configuration());
+
+        let mut restored = Lattice::<XY>::new(36, &rect_fn(6, 1., 1.));
+        let (_, state) = loaded.restore(&mut restored);
+        let mut state = state.unwrap();
+        assert_eq!((state.beta, state.burned_in), (current.beta, current.burned_in));
+        assert_eq!((&state.magnetizations, &state.energies), (&current.magnetizations, &current.energies));
+
+        let mut original = current;
+        let expected = lattice.run_from(&mut original, 100, (usize::MAX, 2.), |_, _| Ok(())).unwrap();
+        let resumed = restored.run_from(&mut state, 100, (usize::MAX, 2.), |_, _| Ok(())).unwrap();
+        assert_eq!(row(0.8, &resumed), row(0.8, &expected));
+    }
+}
@@ -0,0 +1,160 @@
+//! Command-line arguments, read into `Options`
+
+use quasing_model::analysis::{self, Estimator, Peak};
+use quasing_model::config;
+use quasing_model::log::Level;
+use quasing_model::options::{Command, Options, KEYS};
+
+pub const USAGE: &str = "\
+Usage: quasing-model <command> [options]
+
+Commands:
+    run           Run the command named in the --config file
+    sweep         Sweep the temperature on one lattice
+    search        Bisect in beta for the temperature where the magnetization vanishes
+    refine        Sweep coarsely, then add temperatures around the --peak until it is resolved
+    scaling       Sweep every one of --sizes and fit critical exponents by finite-size scaling
+    phase         Sweep the temperature at every value of --parameter and tabulate Tc
+    percolation   Percolation of the diluted lattice at every occupation, and its threshold
+    dilution      Tc of the diluted lattice at every occupation, averaged over --realizations
+    export        Write every lattice family, and a random and diluted lattice of each kind, to data/lattices/
+    render        Equilibrate at --temperature and draw the spins and one cluster as SVG
+
+Options:
+    --config <file>       Read options from a config file; options after it override the file
+    --lattice <family>    rect, square, cubic, bcc, penrose, penrose-cp, einstein, or a .lat file [rect]
+    --size <n>            Side length, Penrose level or cut-and-project radius [family default]
+    --patch <name>        Einstein patch [7k]
+    --eta <x>             Anisotropy of rect and einstein lattices [0]
+    --t1 <J>, --t2 <J>    Couplings of rect and einstein lattices [from eta]
+    --parameter <name>    Option varied across a phase diagram, by flag name or config key,
+                          such as eta, t1 or lattice.size [eta]
+    --from <x>            First value of the phase diagram parameter [-0.99]
+    --to <x>              Last value of the phase diagram parameter [0.99]
+    --steps <n>           Number of values in a phase diagram, or occupations from 0.5 to 1 [40]
+    --values <list>       Comma-separated values of the phase diagram parameter, or occupations
+                          [from --from, --to and --steps]
+    --estimator <name>    Tc estimate of a phase diagram: steepest-descent, susceptibility-quadratic,
+                          susceptibility-lorentzian or specific-heat [steepest-descent]
+    --dilution <kind>     Remove sites or bonds in a dilution or percolation run: site or bond [site]
+    --realizations <n>    Random dilutions at every occupation [8]
+    --spin <model>        ising, xy, heisenberg, or tim for the transverse-field Ising model [ising]
+    --algorithm <name>    wolff, or continuous-wolff for tim [from the spin model]
+    --t-min <T>           Lowest temperature [0.01]
+    --t-max <T>           Highest temperature [4]
+    --betas <n>           Number of temperatures in a sweep [50]
+    --spacing <grid>      Space a sweep evenly in temperature or beta [temperature]
+    --trials <n>          Measurements per temperature [10000]
+    --seed <n>            Random seed; scans add the index of each task [random]
+    --from-snapshot <name>
+                          Start the first temperature of a sweep, or a render, from this snapshot,
+                          named as --snapshots writes it without the .npy or -interfaces.npy suffix
+    --layers <n>          Bisection rounds of a search [4]
+    --per-layer <n>       Temperatures per bisection round [8]
+    --peak <observable>   susceptibility, specific-heat or binder, whose peak a refine resolves [susceptibility]
+    --tolerance <T>       Temperature resolution a refine stops at [0.01]
+    --max-betas <n>       Most temperatures a refine runs [100]
+    --sizes <list>        Comma-separated sizes, or einstein patches, of a scaling run
+    --temperature <T>     Temperature to render at [2]
+    --threads <n>         Worker threads [8]
+    --output <dir>        Output directory [data/output]
+    --name <name>         Output name [derived from the lattice and spin]
+    --resume              Skip finished outputs and continue unfinished sweeps from their checkpoints
+    --series              Write the measurement time series of every temperature
+    --snapshots           Write the configuration at the end of every temperature of a sweep
+    --log-level <level>   error, warn, info or debug [info]
+    --log-file <file>     Also append every log record to this file as JSON lines
+    -h, --help            Print this message
+";
+
+/// Read the command and options from the arguments after the program name. A `--config` file is
+/// read where it appears, so options after it take precedence.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<(Command, Options), String> {
+    let mut args = args.into_iter();
+    let mut command = match args.next().as_deref() {
+        Some("run") => None,
+        Some("-h") | Some("--help") => return Ok((Command::Help, Options::default())),
+        Some(other) => Some(Command::from_name(other).ok_or_else(|| format!("unknown command {:?}", other))?),
+        None => return Err("missing command".to_owned()),
+    };
+
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_owned(), Some(value.to_owned())),
+            None => (arg, None),
+        };
+        if flag == "-h" || flag == "--help" {
+            return Ok((Command::Help, options));
+        }
+        let key = KEYS.iter().find(|(f, _)| *f == flag).map(|(_, key)| *key)
+            .ok_or_else(|| format!("unknown option {:?}", flag))?;
+        let word = match (key, inline) {
+            ("output.resume" | "output.series" | "output.snapshots", None) => "true".to_owned(),
+            (_, Some(word)) => word,
+            (_, None) => args.next().ok_or_else(|| format!("{} needs a value", flag))?,
+        };
+        if key != "config" {
+            options.apply(key, &word)?;
+            continue;
+        }
+        for (key, value) in config::load(&word).map_err(|e| e.to_string())? {
+            if key == "command" {
+                let named = Command::from_name(&value).ok_or_else(|| format!("unknown command {:?} in {}", value, word))?;
+                command.get_or_insert(named);
+            } else {
+                options.apply(&key, &value).map_err(|message| format!("{}: {}", word, message))?;
+            }
+        }
+    }
+    let command = command.ok_or("run needs a --config file that sets the command")?;
+
+    if !["ising", "xy", "heisenberg", "tim"].contains(&options.spin.as_str()) {
+        return Err(format!("unknown spin model {:?}", options.spin));
+    }
+    if let Some(algorithm) = &options.algorithm {
+        let expected = if options.spin == "tim" { "continuous-wolff" } else { "wolff" };
+        if algorithm != expected {
+            return Err(format!("spin model {:?} runs with the {} algorithm, not {:?}", options.spin, expected, algorithm));
+        }
+    }
+    if !["temperature", "beta"].contains(&options.spacing.as_str()) {
+        return Err(format!("--spacing must be temperature or beta, not {:?}", options.spacing));
+    }
+    if Level::from_name(&options.log_level).is_none() {
+        return Err(format!("--log-level must be error, warn, info or debug, not {:?}", options.log_level));
+    }
+    if Peak::from_name(&options.peak).is_none() {
+        return Err(format!("--peak must be susceptibility, specific-heat or binder, not {:?}", options.peak));
+    }
+    if options.lattice != "einstein" && options.sizes.iter().any(|s| s.parse::<usize>().is_err()) {
+        return Err(format!("--sizes must be numbers for {} lattices, got {:?}", options.lattice, options.sizes.join(",")));
+    }
+    if command == Command::Scaling && options.sizes.len() < 2 {
+        return Err("scaling needs at least two --sizes".to_owned());
+    }
+    if options.tolerance <= 0. {
+        return Err(format!("--tolerance must be positive, got {}", options.tolerance));
+    }
+    if !(options.t_min > 0. && options.t_min <= options.t_max) {
+        return Err(format!("need 0 < --t-min <= --t-max, got {} and {}", options.t_min, options.t_max));
+    }
+    if let Some(value) = options.parameter_values().first() {
+        options.at_parameter(*value).map_err(|message| format!("--parameter {}: {}", options.parameter, message))?;
+    }
+    if Estimator::from_name(&options.estimator).is_none() {
+        let names = analysis::ESTIMATORS.map(|estimator| estimator.name());
+        return Err(format!("--estimator must be one of {}, not {:?}", names.join(", "), options.estimator));
+    }
+    if options.dilution != "site" && options.dilution != "bond" {
+        return Err(format!("--dilution must be site or bond, not {:?}", options.dilution));
+    }
+    if options.num_betas == 0 || options.threads == 0 || options.steps == 0 || options.per_layer == 0 || options.realizations == 0 {
+        return Err("--betas, --threads, --steps, --per-layer and --realizations must be positive".to_owned());
+    }
+    if options.trials <= 128 {
+        return Err("--trials must exceed the 128 burn-in measurements".to_owned());
+    }
+    Ok((command, options))
+}
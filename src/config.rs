@@ -0,0 +1,84 @@
+//! Run configuration files, in the subset of TOML made of `[section]` headers and `key = value`
+//! lines whose values are strings, numbers or booleans. `#` starts a comment.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::validate::LoadError;
+
+/// Every `section.key` of the file in order, with its value as text. Strings are unquoted.
+pub fn load(filename: &str) -> Result<Vec<(String, String)>, LoadError> {
+    let file = File::open(filename).map_err(|source| LoadError::Io { file: filename.to_owned(), source })?;
+    let error = |line: usize, message: &str| {
+        LoadError::Parse { file: filename.to_owned(), line: Some(line + 1), message: message.to_owned() }
+    };
+
+    let mut section = String::new();
+    let mut entries = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|source| LoadError::Io { file: filename.to_owned(), source })?;
+        let line = strip_comment(&line).trim();
+        if line.is_empty() { continue; }
+
+        if let Some(name) = line.strip_prefix('[') {
+            section = name.strip_suffix(']').ok_or_else(|| error(i, "unclosed section header"))?.trim().to_owned();
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error(i, "expected key = value"))?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(error(i, &format!("bad key {:?}", key)));
+        }
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => unquote(quoted).ok_or_else(|| error(i, "unterminated string"))?,
+            None if value.is_empty() => return Err(error(i, "missing value")),
+            None => value.to_owned(),
+        };
+        let key = if section.is_empty() { key.to_owned() } else { format!("{}.{}", section, key) };
+        if entries.iter().any(|(k, _)| *k == key) {
+            return Err(error(i, &format!("{} is set twice", key)));
+        }
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// The line up to a `#` that is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Contents of a basic string, given the text after its opening quote
+fn unquote(rest: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().trim().is_empty().then_some(out),
+            '\\' => out.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                other => other,
+            }),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// A string value as it would appear in a config file
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
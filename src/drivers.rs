@@ -0,0 +1,549 @@
+//! Drivers that run a lattice over a range of temperatures and collect its observables, and the
+//! commands built on them. `one_pass`, `search` and `refine` return their results without
+//! touching the filesystem, except `one_pass` when it is given a snapshot or checkpoint file. The
+//! commands take their settings from `Options` and write their outputs next to a config that
+//! reruns them.
+
+use std::{fmt::Display, path::Path, time::Instant};
+
+use crate::analysis::{self, Estimate, Estimator, Located, Peak};
+use crate::checkpoint;
+use crate::config;
+use crate::funcs::{site_dilute_fn, bond_dilute_fn, fibonacci_chain_geometry, fibonacci_grid_geometry,
+    watts_strogatz_geometry, site_diluted_geometry, bond_diluted_geometry, random_regular_fn, degree_matched_fn, erdos_renyi_fn,
+    rect_geometry, cubic_geometry, bcc_geometry, penrose_geometry, einstein_geometry};
+use crate::geometry::{Boundary, Geometry};
+use crate::jobs;
+use crate::lattice::{Lattice, Report, RunState};
+use crate::log::{self, Level, Progress, Record};
+use crate::options::{Command, Options};
+use crate::output::{write_table, Data, Run};
+use crate::percolation;
+use crate::project::{CutProject, BondRule};
+use crate::render::Picture;
+use crate::sampler::Sampler;
+use crate::snapshot::Snapshots;
+use crate::spin::Spin;
+use crate::substitute::{Substitution, Transform};
+use crate::validate::LoadError;
+
+/// Error that stops a command
+#[derive(Debug)]
+pub enum RunError {
+    /// A file could not be read or written
+    File(LoadError),
+    /// The options do not describe a run, such as an unknown lattice
+    Options(String),
+}
+
+impl From<LoadError> for RunError {
+    fn from(error: LoadError) -> Self {
+        RunError::File(error)
+    }
+}
+
+impl Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunError::File(error) => write!(f, "{}", error),
+            RunError::Options(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// `count` evenly spaced values from `start` to `end`
+pub fn linspace(start: f32, end: f32, count: usize) -> Vec<f32> {
+    if count == 1 {
+        return vec![start];
+    }
+    let delta = (end - start) / (count - 1) as f32;
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        out.push(start + delta * i as f32);
+    }
+    out
+}
+
+/// Reciprocals of `count` evenly spaced values from `end` down to `start`
+pub fn reciprocal_linspace(start: f32, end: f32, count: usize) -> Vec<f32> {
+    linspace(end, start, count).into_iter().map(|f|{1./f}).collect::<Vec<_>>()
+}
+
+fn linspace_ex(start: f32, end: f32, count: usize) -> Vec<f32> {
+    if count == 1 {
+        return vec![start];
+    }
+    let delta = (end - start) / (count + 1) as f32;
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        out.push(start + delta * (i + 1) as f32);
+    }
+    out
+}
+
+/// Record of one beta of a run on `lattice`, with the clusters flipped since `counts`
+fn beta_record<L: Sampler>(lattice: &L, beta: f32, report: &Report, counts: (u64, u64), started: Instant) -> (Record, String) {
+    let (clusters, sites) = lattice.cluster_counts();
+    let (clusters, sites) = ((clusters - counts.0) as f64, (sites - counts.1) as f64);
+    let clusters_per_second = clusters / started.elapsed().as_secs_f64();
+    let mean_cluster_size = sites / clusters;
+    let record = Record::new(Level::Info, "beta").number("beta", beta as f64).number("temperature", 1. / beta as f64)
+        .number("measurements", report.num_measurements as f64)
+        .number("clusters_per_second", clusters_per_second).number("mean_cluster_size", mean_cluster_size);
+    let message = format!("T = {:.5}, {:.0} clusters/s of {:.1} sites", 1. / beta, clusters_per_second, mean_cluster_size);
+    (record, message)
+}
+
+/// Runs at each of `betas` in turn, logging progress as `task`. The first beta starts from the
+/// snapshot `from_snapshot` if one is given, and every other beta from `zero`. If a `checkpoint`
+/// file is given, the progress is saved there as the sweep goes, and a checkpoint already there is
+/// continued exactly, unless it belongs to a different sweep.
+pub fn one_pass<L: Sampler>(lattice: &mut L, task: &str, betas: &[f32], n_trials: usize, from_snapshot: Option<&str>,
+    checkpoint: Option<&str>) -> Result<Data, LoadError> {
+    let mut sweep = format!("one_pass {} sites {} betas {:?} trials {}", L::model(), lattice.num_sites(), betas, n_trials);
+    if let Some(snapshot) = from_snapshot {
+        sweep += &format!(" from {}", snapshot);
+    }
+    let mut data = Data::default();
+    let mut current = None;
+    if let Some(filename) = checkpoint.filter(|f| Path::new(f).exists()) {
+        let saved = checkpoint::load(filename)?;
+        if saved.sweep != sweep {
+            return Err(LoadError::Parse { file: filename.to_owned(), line: None,
+                message: format!("belongs to the sweep {:?}, not {:?}", saved.sweep, sweep) });
+        }
+        log::info(&format!("{}: resuming {} after {} betas", task, filename, saved.completed.betas.len()));
+        (data, current) = saved.restore(lattice);
+    }
+
+    let mut progress = Progress::start(task, betas.len(), data.betas.len());
+    for (index, &beta) in betas.iter().enumerate().skip(data.betas.len()) {
+        let (started, counts) = (Instant::now(), lattice.cluster_counts());
+        let mut state = match current.take() {
+            Some(state) => state,
+            None => {
+                match from_snapshot.filter(|_| index == 0) {
+                    Some(snapshot) => lattice.load_snapshot(snapshot)?,
+                    None => lattice.zero(),
+                }
+                RunState::new(beta)
+            },
+        };
+        let report = lattice.run_from(&mut state, n_trials, (n_trials / 10, 0.5), |lattice, state| {
+            if let Some(filename) = checkpoint {
+                checkpoint::save(filename, &sweep, lattice, &data, Some(state))?;
+                Record::new(Level::Debug, "checkpoint").text("task", task).number("measurements", state.magnetizations.len() as f64)
+                    .emit(&format!("{}: checkpoint at T = {:.5} after {} measurements", task, 1. / beta, state.magnetizations.len()));
+            }
+            Ok(())
+        })?;
+        let (record, message) = beta_record(lattice, beta, &report, counts, started);
+        progress.step(record, &message);
+        data.push(beta, report);
+        if let Some(filename) = checkpoint {
+            checkpoint::save(filename, &sweep, lattice, &data, None)?;
+        }
+    }
+    progress.finish();
+    Ok(data)
+}
+
+/// Narrows the beta range from `bottom` to `top` down to the transition over `layers` passes of
+/// `count_per_iteration` betas, each between the last betas clearly disordered and clearly ordered
+pub fn search<L: Sampler>(lattice: &mut L, bottom: f32, top: f32,
+    layers: usize, n_trials: usize, count_per_iteration: usize) -> Result<Data, LoadError> {
+
+    let mut data = Data::default();
+    let mut start = bottom;
+    let mut end = top;
+    for iter_count in 0..layers {
+        let mut new_start = start;
+        let mut new_end = end;
+        let beta_line = if iter_count == 0 {
+            linspace(start, end, count_per_iteration)
+        } else {
+            linspace_ex(start, end, count_per_iteration)
+        };
+        for beta in beta_line {
+            lattice.zero();
+            let result = lattice.run(beta, n_trials, (n_trials / 2, 0.5))?;
+            if result.magnetization < 0.02 {
+                new_start = new_start.max(beta);
+            }
+            if result.magnetization > 0.2 {
+                new_end = new_end.min(beta);
+            }
+            data.push(beta, result);
+
+        }
+        start = new_start;
+        end = new_end;
+    }
+    Ok(data)
+}
+
+/// Sweeps `betas`, then keeps adding temperatures halfway between the ones around the `peak`
+/// until they are no more than `tolerance` apart or `max_betas` have run. Returns the data and the
+/// peak it was refined around.
+pub fn refine<L: Sampler>(lattice: &mut L, task: &str, betas: &[f32], n_trials: usize, peak: Peak,
+    tolerance: f32, max_betas: usize) -> Result<(Data, Option<Located>), LoadError> {
+
+    let mut data = Data::default();
+    let mut pending = betas.to_vec();
+    // The number of betas is not known ahead, so the time left is for the most there may be
+    let mut progress = Progress::start(task, max_betas, 0);
+    loop {
+        for beta in pending.drain(..).take(max_betas.saturating_sub(data.betas.len())) {
+            let (started, counts) = (Instant::now(), lattice.cluster_counts());
+            lattice.zero();
+            let report = lattice.run(beta, n_trials, (n_trials / 10, 0.5))?;
+            let (record, message) = beta_record(lattice, beta, &report, counts, started);
+            progress.step(record, &message);
+            data.push(beta, report);
+        }
+        let located = analysis::locate(&analysis::curve(&data, peak));
+        let Some((low, high)) = located.as_ref().map(|l| l.bracket) else {
+            progress.finish();
+            return Ok((data, None));
+        };
+        log::debug(&format!("{}: {} peak between T = {} and {}", task, peak.name(), low, high));
+        if (high - low) / 2. <= tolerance || data.betas.len() >= max_betas {
+            progress.finish();
+            return Ok((data, located));
+        }
+
+        let mut temps = data.betas.iter().map(|beta| 1. / beta).collect::<Vec<_>>();
+        temps.sort_by(f32::total_cmp);
+        pending = temps.windows(2)
+            .filter(|w| w[0] < high && w[1] > low && w[1] - w[0] > tolerance)
+            .map(|w| 2. / (w[0] + w[1]))
+            .collect();
+        if pending.is_empty() {
+            progress.finish();
+            return Ok((data, located));
+        }
+    }
+}
+
+/// Whether the output `name` was already written, so that resume mode can skip it
+pub fn finished(options: &Options, name: &str) -> bool {
+    options.resume && Path::new(&format!("{}/{}.csv", options.output_dir, name)).exists()
+}
+
+/// `run` with every estimate of Tc from `data` as parameters `tc_{estimator}` and
+/// `tc_{estimator}_error`
+pub fn with_estimates(run: Run, data: &Data) -> Run {
+    analysis::estimates(data).into_iter().fold(run, |run, (name, estimate)| {
+        let name = name.replace('-', "_");
+        run.with_parameter(&format!("tc_{}", name), estimate.temperature)
+            .with_parameter(&format!("tc_{}_error", name), estimate.error)
+    })
+}
+
+/// Seed of the output `name`: the one in `options`, the one recorded in its config when a sweep is
+/// resumed, or a fresh one
+pub fn task_seed(options: &Options, name: &str) -> u64 {
+    let recorded = || {
+        let entries = config::load(&format!("{}/{}.toml", options.output_dir, name)).ok()?;
+        entries.into_iter().find(|(key, _)| key == "run.seed")?.1.parse().ok()
+    };
+    options.seed.or_else(|| if options.resume { recorded() } else { None }).unwrap_or_else(rand::random)
+}
+
+/// Sweeps the temperature range of `options` and saves the output as `name`, checkpointing next
+/// to it, with the resolved config that reruns it through `command`. Without `--resume`, an old
+/// checkpoint is discarded.
+pub fn sweep<L: Sampler>(lattice: &mut L, options: &Options, command: Command, name: &str, run: Run) -> Result<Data, RunError> {
+    let seed = task_seed(options, name);
+    let options = Options { seed: Some(seed), ..options.clone() };
+    let checkpoint = format!("{}/{}.checkpoint", options.output_dir, name);
+    let remove_checkpoint = || std::fs::remove_file(&checkpoint)
+        .map_err(|source| LoadError::Io { file: checkpoint.clone(), source });
+    if !options.resume && Path::new(&checkpoint).exists() {
+        remove_checkpoint()?;
+    }
+    if !Path::new(&checkpoint).exists() {
+        options.save(command, name)?;
+        lattice.seed(seed);
+    }
+    if options.series {
+        lattice.set_series(Some(&format!("{}/{}-series", options.output_dir, name)));
+    }
+    if options.snapshots {
+        lattice.set_snapshots(Some(Snapshots::new(&format!("{}/{}-snapshot", options.output_dir, name))));
+    }
+    let data = one_pass(lattice, name, &options.betas(), options.trials, options.from_snapshot.as_deref(), Some(&checkpoint))?;
+    data.save(&options.output_dir, name, &with_estimates(run.with_seed(seed), &data))?;
+    remove_checkpoint()?;
+    Ok(data)
+}
+
+/// Lattice chosen by `--lattice`, `--size`, `--patch` and `eta`, with the metadata of a run on it
+pub fn build_geometry(options: &Options, eta: f32) -> Result<(Geometry, Run), RunError> {
+    let size = options.size();
+    let (t1, t2) = options.couplings(eta);
+    let with_eta = |run: Run| run.with_parameter("eta", eta).with_parameter("t1", t1).with_parameter("t2", t2);
+    let run = |lattice: &str| Run::new(lattice, &options.spin, options.trials);
+    Ok(match options.lattice.as_str() {
+        "rect" | "square" => (rect_geometry(size, t1, t2), with_eta(run(&format!("rect-{}", size)))),
+        "cubic" => (cubic_geometry(size), run(&format!("cubic-{}", size))),
+        "bcc" => (bcc_geometry(size, 0.), run(&format!("bcc-{}", size))),
+        "penrose" => (penrose_geometry(size)?, run(&format!("penrose-{}", size))),
+        "penrose-cp" => (
+            CutProject::penrose().generate(size as f32, BondRule::Hyperlattice)
+                .geometry(1., &format!("penrose cut-and-project radius {}", size)),
+            run(&format!("penrose-r{}", size)),
+        ),
+        "einstein" => (
+            einstein_geometry(&options.patch, t1, t2)?,
+            with_eta(run(&format!("einstein-{}", options.patch))),
+        ),
+        filename if filename.ends_with(".lat") => {
+            let geometry = Geometry::load(filename)?;
+            let name = filename.rsplit('/').next().unwrap().trim_end_matches(".lat").to_owned();
+            (geometry, run(&name))
+        },
+        other => return Err(RunError::Options(format!("unknown lattice {:?}", other))),
+    })
+}
+
+/// Output name from `--name`, or the lattice and spin
+pub fn output_name(options: &Options, run: &Run) -> String {
+    options.name.clone().unwrap_or_else(|| format!("{}-{}", run.lattice, run.spin))
+}
+
+/// Sweeps the lattice of `options` once, unless `--resume` finds it finished
+pub fn one_sweep<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let name = output_name(options, &run);
+    if finished(options, &name) { return Ok(()); }
+    log::info(&format!("{} sites from {}", geometry.num_sites(), geometry.provenance));
+    let mut lattice = L::from_geometry(&geometry);
+    sweep(&mut lattice, options, Command::Sweep, &name, run)?;
+    Ok(())
+}
+
+/// Bisects for the transition of the lattice of `options` with `search`
+pub fn one_search<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let name = format!("{}-search", output_name(options, &run));
+    let seed = task_seed(options, &name);
+    log::info(&format!("{} sites from {}", geometry.num_sites(), geometry.provenance));
+    Options { seed: Some(seed), ..options.clone() }.save(Command::Search, &name)?;
+    let mut lattice = L::from_geometry(&geometry);
+    lattice.seed(seed);
+    let data = search(&mut lattice, 1. / options.t_max, 1. / options.t_min, options.layers, options.trials, options.per_layer)?;
+    data.save(&options.output_dir, &name, &run.with_seed(seed))?;
+    Ok(())
+}
+
+/// Resolves the `--peak` of the lattice of `options` with `refine`, recording Tc if it is found
+pub fn one_refine<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let name = format!("{}-refine", output_name(options, &run));
+    let seed = task_seed(options, &name);
+    log::info(&format!("{} sites from {}", geometry.num_sites(), geometry.provenance));
+    Options { seed: Some(seed), ..options.clone() }.save(Command::Refine, &name)?;
+    let mut lattice = L::from_geometry(&geometry);
+    lattice.seed(seed);
+    let peak = Peak::from_name(&options.peak).ok_or_else(|| RunError::Options(format!("unknown peak {:?}", options.peak)))?;
+    let (data, located) = refine(&mut lattice, &name, &options.betas(), options.trials, peak, options.tolerance, options.max_betas)?;
+    let mut run = with_estimates(run.with_seed(seed), &data);
+    match located {
+        Some(located) => {
+            let (low, high) = located.bracket;
+            let Estimate { temperature, error } = located.estimate;
+            log::info(&format!("Tc = {} +/- {} from the {} peak, bracketed by {} and {}", temperature, error, peak.name(), low, high));
+            if (high - low) / 2. > options.tolerance {
+                log::warn(&format!("the peak is not resolved to {} within {} temperatures", options.tolerance, options.max_betas));
+            }
+            if located.at_edge {
+                log::warn(&format!("the {} peak is at the edge of the temperature range", peak.name()));
+            }
+            run = run.with_parameter("tc", temperature).with_parameter("tc_error", error);
+        },
+        None => log::warn(&format!("too few temperatures to locate the {} peak", peak.name())),
+    }
+    data.sorted().save(&options.output_dir, &name, &run)?;
+    Ok(())
+}
+
+/// Neighbor function of a lattice built at run time
+type NeighborFn<'a> = Box<dyn Fn(usize) -> Vec<(usize, f32)> + 'a>;
+
+/// The lattice of `neighbor_func` with each site, or each bond by `--dilution bond`, kept with
+/// probability `occupation`, as the original index of every kept site and the neighbor function
+/// of the diluted lattice
+fn dilute<'a, F: Fn(usize) -> Vec<(usize, f32)> + 'a>(kind: &str, num_sites: usize, neighbor_func: &'a F, occupation: f32,
+    seed: u64) -> (Vec<usize>, NeighborFn<'a>) {
+
+    match kind {
+        "bond" => ((0..num_sites).collect(), Box::new(bond_dilute_fn(num_sites, neighbor_func, occupation, seed))),
+        _ => {
+            let (kept, func) = site_dilute_fn(num_sites, neighbor_func, occupation, seed);
+            (kept, Box::new(func))
+        },
+    }
+}
+
+/// Percolation of the lattice of `options`, which needs positions, diluted `--realizations` times
+/// at every occupation, with the threshold where its two ends along the first axis connect
+pub fn percolation_scan(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let name = format!("{}-{}-percolation", output_name(options, &run), options.dilution);
+    if finished(options, &name) { return Ok(()); }
+    if geometry.dimension() == 0 {
+        return Err(RunError::Options(format!("percolation needs site positions, which {} has not", geometry.provenance)));
+    }
+    if geometry.boundary != Boundary::Open {
+        log::warn("the lattice is periodic, so clusters that wrap around span trivially");
+    }
+    let seed = task_seed(options, &name);
+    Options { seed: Some(seed), ..options.clone() }.save(Command::Percolation, &name)?;
+    let size = geometry.num_sites();
+    log::info(&format!("{} sites from {}", size, geometry.provenance));
+
+    let xs = geometry.positions.iter().map(|position| position[0]);
+    let (low, high) = xs.fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), x| (low.min(x), high.max(x)));
+    let margin = 0.05 * (high - low);
+    let mut sides = [Vec::new(), Vec::new()];
+    for (i, position) in geometry.positions.iter().enumerate() {
+        if position[0] < low + margin { sides[0].push(i); }
+        if position[0] > high - margin { sides[1].push(i); }
+    }
+
+    let adjacency = geometry.adjacency();
+    let couplings = geometry.couplings();
+    let func = adjacency.neighbor_fn(&couplings);
+    let samples = options.realizations;
+    let results = options.occupations().into_iter().enumerate().map(|(index, occupation)| {
+        let first = seed.wrapping_add((index * samples) as u64);
+        percolation::percolation(size, occupation, samples,
+            |sample| dilute(&options.dilution, size, &func, occupation, first.wrapping_add(sample)), &sides)
+    }).collect::<Vec<_>>();
+    log::info(&format!("percolation threshold {:?}", percolation::threshold(&results)));
+    percolation::save(&results, &options.output_dir, &name)?;
+    Ok(())
+}
+
+/// Tc of the lattice of `options` diluted `--realizations` times at every occupation, averaged
+/// over the realizations. Each realization is a task for the `--threads` workers with its own
+/// seed, counted up from the seed of the run, that picks both its dilution and its sweep. The
+/// error of the average combines the spread between realizations with their own errors.
+pub fn dilution<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let base = output_name(options, &run);
+    let name = format!("{}-{}-dilution", base, options.dilution);
+    if finished(options, &name) { return Ok(()); }
+    let estimator = Estimator::from_name(&options.estimator)
+        .ok_or_else(|| RunError::Options(format!("unknown estimator {:?}", options.estimator)))?;
+    let seed = task_seed(options, &name);
+    let options = Options { seed: Some(seed), ..options.clone() };
+    options.save(Command::Dilution, &name)?;
+    let size = geometry.num_sites();
+    log::info(&format!("{} {} dilution of {} sites from {}", L::model(), options.dilution, size, geometry.provenance));
+
+    let adjacency = geometry.adjacency();
+    let couplings = geometry.couplings();
+    let func = adjacency.neighbor_fn(&couplings);
+    let occupations = options.occupations();
+    let realizations = options.realizations;
+    let tasks = occupations.iter().flat_map(|occupation| (0..realizations).map(move |r| (*occupation, r))).collect();
+    let estimates = jobs::run(tasks, options.threads, |index, (occupation, realization)| -> Result<_, RunError> {
+        let name = format!("{}-{}{:.8}-r{}", base, options.dilution, occupation, realization);
+        let seed = seed.wrapping_add(index as u64);
+        let task = Options { seed: Some(seed), ..options.clone() };
+        let data = if finished(&task, &name) {
+            Data::load(&task.output_dir, &name)?
+        } else {
+            let (kept, func) = dilute(&options.dilution, size, &func, occupation, seed);
+            let mut lattice = L::new(kept.len(), &func);
+            let run = run.clone().with_parameter("occupation", occupation)
+                .with_parameter("realization", realization as f32)
+                .with_parameter("kept_fraction", kept.len() as f32 / size as f32);
+            sweep(&mut lattice, &task, Command::Dilution, &name, run)?
+        };
+        Ok(analysis::estimate(&data, estimator))
+    }).into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let columns = ["occupation", "tc", "tc_error", "tc_spread", "realizations"];
+    let rows = occupations.iter().zip(estimates.chunks(realizations)).map(|(occupation, estimates)| {
+        let found = estimates.iter().flatten().collect::<Vec<_>>();
+        if found.len() < estimates.len() {
+            log::warn(&format!("{} of {} realizations at occupation {} have no {} estimate",
+                estimates.len() - found.len(), estimates.len(), occupation, options.estimator));
+        }
+        let n = found.len() as f32;
+        let temperatures = found.iter().map(|e| e.temperature).collect::<Vec<_>>();
+        let tc = temperatures.iter().sum::<f32>() / n;
+        let spread = if found.is_empty() { f32::NAN } else { analysis::spread(&temperatures) };
+        let variance = found.iter().map(|e| e.error * e.error).sum::<f32>() / n;
+        format!("{},{},{},{},{}", occupation, tc, ((spread * spread + variance) / n).sqrt(), spread, found.len())
+    });
+    let run = run.with_seed(seed).with_parameter("realizations", realizations as f32).with_columns(&columns);
+    write_table(&options.output_dir, &name, &columns.join(","), rows, &run)?;
+    Ok(())
+}
+
+/// Write every lattice family, and one instance of each random and diluted lattice, to
+/// `data/lattices/` in the geometry file format
+pub fn export_lattices() -> Result<(), RunError> {
+    let dir = "data/lattices";
+    std::fs::create_dir_all(dir).map_err(|source| LoadError::Io { file: dir.to_owned(), source })?;
+    let square = rect_geometry(128, 1., 1.);
+    square.save("data/lattices/square-128.lat")?;
+    cubic_geometry(16).save("data/lattices/cubic-16.lat")?;
+    bcc_geometry(16, 0.).save("data/lattices/bcc-16.lat")?;
+    let penrose = CutProject::penrose().generate(40., BondRule::Hyperlattice)
+        .geometry(1., "penrose cut-and-project radius 40");
+    penrose.save("data/lattices/penrose-r40.lat")?;
+    fibonacci_chain_geometry(144, 1., 0.5, true).map_err(RunError::Options)?.save("data/lattices/fibonacci-chain-144.lat")?;
+    fibonacci_grid_geometry(89, 1., 0.5, true).map_err(RunError::Options)?.save("data/lattices/fibonacci-grid-89.lat")?;
+    watts_strogatz_geometry(128, 1., 1., 0.1, 1).save("data/lattices/watts-strogatz-128.lat")?;
+    site_diluted_geometry(&penrose, 0.8, 1).save("data/lattices/penrose-r40-site-0.8.lat")?;
+    bond_diluted_geometry(&square, 0.8, 1).save("data/lattices/square-128-bond-0.8.lat")?;
+    fn graph(num_sites: usize, func: Result<impl Fn(usize) -> Vec<(usize, f32)>, String>, provenance: &str) -> Result<Geometry, RunError> {
+        Ok(Geometry::from_fn(num_sites, &func.map_err(RunError::Options)?, Boundary::Open, provenance))
+    }
+    graph(4096, random_regular_fn(4096, 3, 1), "random_regular_fn 4096 degree=3 seed=1")?
+        .save("data/lattices/random-regular-3-4096.lat")?;
+    let (adjacency, couplings) = (penrose.adjacency(), penrose.couplings());
+    graph(penrose.num_sites(), degree_matched_fn(penrose.num_sites(), &adjacency.neighbor_fn(&couplings), 1),
+        "degree_matched_fn seed=1 of penrose cut-and-project radius 40")?
+        .save("data/lattices/degree-matched-penrose-r40.lat")?;
+    graph(4096, Ok(erdos_renyi_fn(4096, 4., 1)), "erdos_renyi_fn 4096 mean_degree=4 seed=1")?
+        .save("data/lattices/erdos-renyi-4-4096.lat")?;
+    CutProject::icosahedral().generate(10., BondRule::Hyperlattice)
+        .geometry(1., "icosahedral cut-and-project radius 10")
+        .save("data/lattices/icosahedral-r10.lat")?;
+    Substitution::chair().generate(&[(0, Transform::identity())], 6, 1e-3)
+        .geometry(&[1.], "chair substitution level 6")
+        .save("data/lattices/chair-6.lat")?;
+    penrose_geometry(9)?.save("data/lattices/penrose-9.lat")?;
+    einstein_geometry("7k", 1., 1.)?.save("data/lattices/einstein-7k.lat")?;
+    Ok(())
+}
+
+/// Equilibrate the lattice of `options`, which needs positions, at `--temperature`, then draw its
+/// spins, and again with one Wolff cluster highlighted
+pub fn render_domains<S: Spin>(options: &Options) -> Result<(), RunError> {
+    let (geometry, run) = build_geometry(options, options.eta)?;
+    let temp = options.temperature;
+    let name = format!("{}-T{}", output_name(options, &run), temp);
+    let seed = task_seed(options, &name);
+    Options { seed: Some(seed), ..options.clone() }.save(Command::Render, &name)?;
+    let mut lattice = Lattice::<S>::from_geometry(&geometry);
+    lattice.seed(seed);
+    match &options.from_snapshot {
+        Some(snapshot) => lattice.load_snapshot(snapshot)?,
+        None => lattice.zero(),
+    }
+    lattice.run(1. / temp, options.trials, (usize::MAX, 2.))?;
+    let prefix = format!("{}/{}", options.output_dir, name);
+    Picture::new(&geometry).with_spins(lattice.spins()).save(&format!("{}.svg", prefix))?;
+    let cluster = lattice.wolff_cluster(1. / temp);
+    Picture::new(&geometry).with_spins(lattice.spins()).with_cluster(&cluster).save(&format!("{}-cluster.svg", prefix))?;
+    Ok(())
+}
@@ -1,17 +1,35 @@
-use std::io::Read;
+use std::collections::{BinaryHeap, HashSet};
 
-use npy::NpyData;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
 
-use crate::spin::SmallVec;
+use crate::adjacency::Adjacency;
+use crate::geometry::{Geometry, Boundary};
+use crate::validate::LoadError;
+use crate::project::{CutProject, BondRule};
 
-pub fn square_fn(row_size: usize) -> impl Fn(usize) -> SmallVec<(usize, f32)> {
+/// Positions of the sites of `rect_fn`, `cubic_fn` and the Fibonacci lattices, where site
+/// `x + row_size * y + ...` sits at `(x, y, ...)`
+pub fn grid_positions(row_size: usize, dimension: usize) -> Vec<Vec<f32>> {
+    (0..row_size.pow(dimension as u32)).map(|site| {
+        (0..dimension).map(|axis| ((site / row_size.pow(axis as u32)) % row_size) as f32).collect()
+    }).collect()
+}
+
+/// Positions of the sites of `bcc_fn`
+pub fn bcc_positions(row_size: usize) -> Vec<Vec<f32>> {
+    let corners = grid_positions(row_size, 3);
+    let centers = corners.iter().map(|p| p.iter().map(|x| x + 0.5).collect());
+    corners.clone().into_iter().chain(centers).collect()
+}
+
+pub fn square_fn(row_size: usize) -> impl Fn(usize) -> Vec<(usize, f32)> {
     rect_fn(row_size, 1., 1.)
 }
 
-pub fn rect_fn(row_size: usize, t1: f32, t2: f32) -> impl Fn(usize) -> SmallVec<(usize, f32)> {
+pub fn rect_fn(row_size: usize, t1: f32, t2: f32) -> impl Fn(usize) -> Vec<(usize, f32)> {
     move |site: usize| {
-        let mut neighbors = SmallVec::new();
-        if site % row_size != 0 {
+        let mut neighbors = Vec::new();
+        if !site.is_multiple_of(row_size) {
             neighbors.push((site - 1, 1./(t1*t1)))
         } else {
             neighbors.push((site + row_size - 1, 1./(t1*t1)))
@@ -35,82 +53,551 @@ pub fn rect_fn(row_size: usize, t1: f32, t2: f32) -> impl Fn(usize) -> SmallVec<
     }
 }
 
-pub fn load_penrose(level: usize) -> (usize, impl Fn(usize) ->SmallVec<(usize, f32)>) {
-    let mut buf = vec![];
-    std::fs::File::open(format!("data/penrose-{}.npy", level)).unwrap()
-        .read_to_end(&mut buf).unwrap();
-    let bond_indices = NpyData::<i64>::from_bytes(&buf).unwrap().to_vec();
-    let mut max_bond_index = 0;
-    for i in &bond_indices {
-        max_bond_index = max_bond_index.max(*i);
-    }
-    (
-        max_bond_index as usize + 1,
-        move |site: usize| {
-            let mut neighbors = SmallVec::new();
-            for pair in bond_indices.chunks(2) {
-                if pair[0] as usize == site {
-                    neighbors.push((pair[1] as usize, 1.0));
-                }
-                if pair[1] as usize == site {
-                    neighbors.push((pair[0] as usize, 1.0));
-                }
+/// Couplings of the first `length` letters of the Fibonacci word ABAABABA..., made by the
+/// substitution A -> AB, B -> A.
+fn fibonacci_word(length: usize, j_a: f32, j_b: f32) -> Vec<f32> {
+    let mut word = vec![true];
+    while word.len() < length {
+        word = word.iter().flat_map(|a| if *a { vec![true, false] } else { vec![true] }).collect();
+    }
+    word.into_iter().take(length).map(|a| if a { j_a } else { j_b }).collect()
+}
+
+/// Checks the number of sites along a Fibonacci chain. A periodic chain is a periodic approximant
+/// of the quasicrystal only if its length is a Fibonacci number, since only then does the word
+/// repeat seamlessly across the wraparound bond, and it needs three sites for that bond to be new.
+fn check_fibonacci_length(length: usize, periodic: bool) -> Result<(), String> {
+    if length == 0 {
+        return Err("a Fibonacci chain needs at least one site".to_owned());
+    }
+    if periodic {
+        let (mut a, mut b) = (1, 2);
+        while b < length {
+            (a, b) = (b, a + b);
+        }
+        if length < 3 || b != length {
+            return Err(format!("a periodic Fibonacci chain needs a Fibonacci number of at least 3 sites, not {}", length));
+        }
+    }
+    Ok(())
+}
+
+/// Fibonacci chain of `length` sites, where bond `k` between sites `k` and `k+1` has coupling `j_a`
+/// or `j_b` following the Fibonacci word. If `periodic`, the last site is bonded to the first by
+/// letter `length - 1`, which makes the periodic approximant; `length` must then be a Fibonacci
+/// number of at least 3.
+pub fn fibonacci_chain_fn(length: usize, j_a: f32, j_b: f32, periodic: bool) -> Result<impl Fn(usize) -> Vec<(usize, f32)>, String> {
+    check_fibonacci_length(length, periodic)?;
+    let couplings = fibonacci_word(length, j_a, j_b);
+    Ok(move |site: usize| {
+        let mut neighbors = Vec::new();
+        if site != 0 {
+            neighbors.push((site - 1, couplings[site - 1]))
+        } else if periodic {
+            neighbors.push((length - 1, couplings[length - 1]))
+        }
+        if site != length - 1 {
+            neighbors.push((site + 1, couplings[site]))
+        } else if periodic {
+            neighbors.push((0, couplings[site]))
+        }
+        neighbors
+    })
+}
+
+/// Square grid of `row_size^2` sites whose rows and columns are both Fibonacci chains. The bond
+/// from column `x` to `x+1` has the coupling of letter `x`, and likewise for rows. `row_size`
+/// is checked as the length of `fibonacci_chain_fn` is.
+pub fn fibonacci_grid_fn(row_size: usize, j_a: f32, j_b: f32, periodic: bool) -> Result<impl Fn(usize) -> Vec<(usize, f32)>, String> {
+    check_fibonacci_length(row_size, periodic)?;
+    let couplings = fibonacci_word(row_size, j_a, j_b);
+    Ok(move |site: usize| {
+        let (x, y) = (site % row_size, site / row_size);
+        let mut neighbors = Vec::new();
+        if x != 0 {
+            neighbors.push((site - 1, couplings[x - 1]))
+        } else if periodic {
+            neighbors.push((site + row_size - 1, couplings[row_size - 1]))
+        }
+        if x != row_size - 1 {
+            neighbors.push((site + 1, couplings[x]))
+        } else if periodic {
+            neighbors.push((site + 1 - row_size, couplings[x]))
+        }
+        if y != 0 {
+            neighbors.push((site - row_size, couplings[y - 1]))
+        } else if periodic {
+            neighbors.push((site + row_size * (row_size - 1), couplings[row_size - 1]))
+        }
+        if y != row_size - 1 {
+            neighbors.push((site + row_size, couplings[y]))
+        } else if periodic {
+            neighbors.push((x, couplings[y]))
+        }
+        neighbors
+    })
+}
+
+fn adjacency_fn(adjacency: Vec<Vec<(usize, f32)>>) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    move |site: usize| { adjacency[site].clone() }
+}
+
+/// Double-edge swaps per bond that `configuration_model` makes to randomize its starting graph
+const SWAPS_PER_BOND: usize = 20;
+
+/// Erdos-Gallai test: whether some simple graph has the degree sequence `degrees`
+fn is_graphical(degrees: &[usize]) -> bool {
+    let mut sorted = degrees.to_vec();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    let n = sorted.len();
+    let mut suffix = vec![0; n + 1];
+    for i in (0..n).rev() {
+        suffix[i] = suffix[i + 1] + sorted[i];
+    }
+    if !suffix[0].is_multiple_of(2) { return false; }
+    // `at_least` counts the sites of degree at least k, which shrinks as k grows
+    let (mut prefix, mut at_least) = (0, n);
+    for k in 1..=n {
+        prefix += sorted[k - 1];
+        while at_least > 0 && sorted[at_least - 1] < k {
+            at_least -= 1;
+        }
+        let split = at_least.max(k);
+        if prefix > k * (k - 1) + k * (split - k) + suffix[split] { return false; }
+    }
+    true
+}
+
+/// Havel-Hakimi graph: a simple graph where site `i` has degree `degrees[i]`, built by bonding
+/// the site with the most unbonded stubs to the sites with the next most, until none are left.
+/// Returns its bonds, or `None` if it gets stuck, which happens exactly when no simple graph has
+/// these degrees.
+fn havel_hakimi(degrees: &[usize]) -> Option<Vec<(usize, usize)>> {
+    let mut heap = degrees.iter().enumerate().filter(|(_, d)| **d > 0).map(|(site, d)| (*d, site)).collect::<BinaryHeap<_>>();
+    let mut bonds = Vec::new();
+    while let Some((stubs, site)) = heap.pop() {
+        let partners = (0..stubs).map(|_| heap.pop()).collect::<Option<Vec<_>>>()?;
+        for (remaining, partner) in partners {
+            bonds.push((site, partner));
+            if remaining > 1 {
+                heap.push((remaining - 1, partner));
             }
-            neighbors
-        }
-    )
-}
-
-pub fn load_einstein(name: &str, t1: f32, t2: f32) -> (usize, impl Fn(usize) ->SmallVec<(usize, f32)>) {
-    let mut small_buf = vec![];
-    let mut med_buf = vec![];
-    let mut long_buf = vec![];
-    std::fs::File::open(format!("data/einstein-{}-short.npy", name)).unwrap()
-        .read_to_end(&mut small_buf).unwrap();
-    let small_indices = NpyData::<i64>::from_bytes(&small_buf).unwrap().to_vec();
-    
-    std::fs::File::open(format!("data/einstein-{}-medium.npy", name)).unwrap()
-        .read_to_end(&mut med_buf).unwrap();
-    let medium_indices = NpyData::<i64>::from_bytes(&med_buf).unwrap().to_vec();
-
-    std::fs::File::open(format!("data/einstein-{}-long.npy", name)).unwrap()
-    .read_to_end(&mut long_buf).unwrap();
-    let large_indices = NpyData::<i64>::from_bytes(&long_buf).unwrap().to_vec();
-
-    let size = small_indices.iter().max().max(
-        medium_indices.iter().max().max(
-            large_indices.iter().max())
-    ).unwrap();
-
-    (
-        *size as usize + 1,
-        move |site: usize| {
-            let mut neighbors = SmallVec::new();
-            for pair in small_indices.chunks(2) {
-                if pair[0] as usize == site {
-                    neighbors.push((pair[1] as usize, 1.0/(t1*t1)));
-                }
-                if pair[1] as usize == site {
-                    neighbors.push((pair[0] as usize, 1.0/(t1*t1)));
-                }
+        }
+    }
+    Some(bonds)
+}
+
+/// Configuration model: a random simple graph with unit couplings where site `i` has degree
+/// `degrees[i]`. It starts from the `havel_hakimi` graph and makes `SWAPS_PER_BOND` double-edge
+/// swaps per bond, each replacing bonds `a-b` and `c-d` by `a-d` and `c-b` unless that makes a
+/// self-loop or a repeated bond. The swaps connect every simple graph with these degrees and
+/// propose each one as often as its reverse, so the chain samples them uniformly, even for dense
+/// graphs where pairing stubs at random almost never gives a simple graph. Fails if no simple
+/// graph has these degrees.
+fn configuration_model(degrees: &[usize], rng: &mut StdRng) -> Result<Vec<Vec<(usize, f32)>>, String> {
+    if !is_graphical(degrees) {
+        return Err("no simple graph has this degree sequence".to_owned());
+    }
+    let mut bonds = havel_hakimi(degrees).ok_or("no simple graph has this degree sequence")?;
+    let key = |a: usize, b: usize| (a.min(b), a.max(b));
+    let mut bond_set = bonds.iter().map(|&(a, b)| key(a, b)).collect::<HashSet<_>>();
+    if bonds.len() >= 2 {
+        for _ in 0..SWAPS_PER_BOND * bonds.len() {
+            let (i, j) = (rng.gen_range(0..bonds.len()), rng.gen_range(0..bonds.len()));
+            let ((a, b), (mut c, mut d)) = (bonds[i], bonds[j]);
+            if rng.gen::<bool>() {
+                (c, d) = (d, c);
             }
-            for pair in medium_indices.chunks(2) {
-                if pair[0] as usize == site {
-                    neighbors.push((pair[1] as usize, 1./(t2*t2)));
-                }
-                if pair[1] as usize == site {
-                    neighbors.push((pair[0] as usize, 1./(t2*t2)));
-                }
+            if i == j || a == d || c == b || bond_set.contains(&key(a, d)) || bond_set.contains(&key(c, b)) {
+                continue;
             }
-            for pair in large_indices.chunks(2) {
-                if pair[0] as usize == site {
-                    neighbors.push((pair[1] as usize, 0.25/(t1*t1)));
-                }
-                if pair[1] as usize == site {
-                    neighbors.push((pair[0] as usize, 0.25/(t1*t1)));
-                }
+            bond_set.remove(&key(a, b));
+            bond_set.remove(&key(c, d));
+            bond_set.insert(key(a, d));
+            bond_set.insert(key(c, b));
+            bonds[i] = (a, d);
+            bonds[j] = (c, b);
+        }
+    }
+    let mut adjacency = vec![Vec::new(); degrees.len()];
+    for (a, b) in bonds {
+        adjacency[a].push((b, 1.));
+        adjacency[b].push((a, 1.));
+    }
+    Ok(adjacency)
+}
+
+/// Random `degree`-regular graph on `num_sites` sites with unit couplings. Fails if there is none,
+/// e.g. when `degree` is not below `num_sites`.
+pub fn random_regular_fn(num_sites: usize, degree: usize, seed: u64) -> Result<impl Fn(usize) -> Vec<(usize, f32)>, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Ok(adjacency_fn(configuration_model(&vec![degree; num_sites], &mut rng)?))
+}
+
+/// Random graph with unit couplings and the same degree sequence as the lattice given by
+/// `neighbor_func`, but with the sites shuffled. This keeps the coordination of a tiling while
+/// removing its order.
+pub fn degree_matched_fn(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>, seed: u64) -> Result<impl Fn(usize) -> Vec<(usize, f32)>, String> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut degrees = (0..num_sites).map(|site| neighbor_func(site).len()).collect::<Vec<_>>();
+    degrees.shuffle(&mut rng);
+    Ok(adjacency_fn(configuration_model(&degrees, &mut rng)?))
+}
+
+/// Erdos-Renyi graph on `num_sites` sites where each pair is bonded with unit coupling with
+/// probability `mean_degree / (num_sites - 1)`. Pairs are visited with geometric skips, so this
+/// takes time proportional to the number of bonds. Fewer than two sites have no bonds.
+pub fn erdos_renyi_fn(num_sites: usize, mean_degree: f32, seed: u64) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let prob = if num_sites < 2 { 0. } else { (mean_degree / (num_sites - 1) as f32).min(1.) };
+    let mut adjacency = vec![Vec::new(); num_sites];
+    if prob > 0. {
+        // Pair (a, b) with b < a is visited in order of a, then b
+        let (mut a, mut b) = (1, 0);
+        loop {
+            let skip = if prob < 1. {
+                ((1. - rng.gen::<f32>()).ln() / (1. - prob).ln()).floor() as usize
+            } else {
+                0
+            };
+            b += skip;
+            while b >= a && a < num_sites {
+                b -= a;
+                a += 1;
+            }
+            if a >= num_sites { break; }
+            adjacency[a].push((b, 1.));
+            adjacency[b].push((a, 1.));
+            b += 1;
+        }
+    }
+    adjacency_fn(adjacency)
+}
+
+/// Watts-Strogatz small world made from the `rect_fn` torus. Each bond keeps one end and, with
+/// probability `rewire_prob`, moves its other end to a random site, avoiding self-loops and
+/// repeated bonds. Couplings are carried along with the bonds.
+pub fn watts_strogatz_fn(row_size: usize, t1: f32, t2: f32, rewire_prob: f32, seed: u64) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let num_sites = row_size * row_size;
+    let lattice = rect_fn(row_size, t1, t2);
+    let mut bonds = Vec::new();
+    let mut bond_set = HashSet::new();
+    for site in 0..num_sites {
+        for (neighbor, strength) in lattice(site).iter() {
+            if site < *neighbor {
+                bonds.push((site, *neighbor, *strength));
+                bond_set.insert((site, *neighbor));
+            }
+        }
+    }
+    for bond in bonds.iter_mut() {
+        if rng.gen::<f32>() >= rewire_prob { continue; }
+        let target = rng.gen_range(0..num_sites);
+        let key = (bond.0.min(target), bond.0.max(target));
+        if target == bond.0 || bond_set.contains(&key) { continue; }
+        bond_set.remove(&(bond.0.min(bond.1), bond.0.max(bond.1)));
+        bond_set.insert(key);
+        bond.1 = target;
+    }
+    let mut adjacency = vec![Vec::new(); num_sites];
+    for (a, b, strength) in bonds {
+        adjacency[a].push((b, strength));
+        adjacency[b].push((a, strength));
+    }
+    adjacency_fn(adjacency)
+}
+
+/// Site dilution of any lattice: each site is kept with probability `occupation`, and the kept
+/// sites are relabeled in order. Returns the original index of every kept site along with the
+/// neighbor function of the diluted lattice.
+pub fn site_dilute_fn(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>, occupation: f32, seed: u64) -> (Vec<usize>, impl Fn(usize) -> Vec<(usize, f32)>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let kept = (0..num_sites).filter(|_| rng.gen::<f32>() < occupation).collect::<Vec<_>>();
+    let mut new_index = vec![None; num_sites];
+    for (i, site) in kept.iter().enumerate() {
+        new_index[*site] = Some(i);
+    }
+    let adjacency = kept.iter().map(|site| {
+        neighbor_func(*site).iter()
+            .filter_map(|(neighbor, strength)| new_index[*neighbor].map(|n| (n, *strength)))
+            .collect()
+    }).collect();
+    (kept, adjacency_fn(adjacency))
+}
+
+/// Bond dilution of any lattice: each bond is kept with probability `occupation`. Both directions
+/// of a bond are kept or removed together.
+pub fn bond_dilute_fn(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>, occupation: f32, seed: u64) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut adjacency = vec![Vec::new(); num_sites];
+    for site in 0..num_sites {
+        for (neighbor, strength) in neighbor_func(site).iter() {
+            if site < *neighbor && rng.gen::<f32>() < occupation {
+                adjacency[site].push((*neighbor, *strength));
+                adjacency[*neighbor].push((site, *strength));
+            }
+        }
+    }
+    adjacency_fn(adjacency)
+}
+
+/// Geometry of the `size` x `size` rectangular torus of `rect_fn`
+pub fn rect_geometry(size: usize, t1: f32, t2: f32) -> Geometry {
+    Geometry::from_fn(size*size, &rect_fn(size, t1, t2), Boundary::Periodic(vec![size as f32; 2]),
+        &format!("rect_fn {} t1={} t2={}", size, t1, t2)).with_positions(grid_positions(size, 2))
+}
+
+/// Geometry of the simple cubic torus of `cubic_fn`
+pub fn cubic_geometry(size: usize) -> Geometry {
+    Geometry::from_fn(size*size*size, &cubic_fn(size), Boundary::Periodic(vec![size as f32; 3]),
+        &format!("cubic_fn {}", size)).with_positions(grid_positions(size, 3))
+}
+
+/// Geometry of the body-centered cubic torus of `bcc_fn`
+pub fn bcc_geometry(size: usize, j2: f32) -> Geometry {
+    Geometry::from_fn(2*size*size*size, &bcc_fn(size, j2), Boundary::Periodic(vec![size as f32; 3]),
+        &format!("bcc_fn {} j2={}", size, j2)).with_positions(bcc_positions(size))
+}
+
+/// Geometry of the Fibonacci chain of `fibonacci_chain_fn`, with unit spacing
+pub fn fibonacci_chain_geometry(length: usize, j_a: f32, j_b: f32, periodic: bool) -> Result<Geometry, String> {
+    let boundary = if periodic { Boundary::Periodic(vec![length as f32]) } else { Boundary::Open };
+    Ok(Geometry::from_fn(length, &fibonacci_chain_fn(length, j_a, j_b, periodic)?, boundary,
+        &format!("fibonacci_chain_fn {} j_a={} j_b={} periodic={}", length, j_a, j_b, periodic))
+        .with_positions(grid_positions(length, 1)))
+}
+
+/// Geometry of the Fibonacci grid of `fibonacci_grid_fn`, with unit spacing
+pub fn fibonacci_grid_geometry(size: usize, j_a: f32, j_b: f32, periodic: bool) -> Result<Geometry, String> {
+    let boundary = if periodic { Boundary::Periodic(vec![size as f32; 2]) } else { Boundary::Open };
+    Ok(Geometry::from_fn(size*size, &fibonacci_grid_fn(size, j_a, j_b, periodic)?, boundary,
+        &format!("fibonacci_grid_fn {} j_a={} j_b={} periodic={}", size, j_a, j_b, periodic))
+        .with_positions(grid_positions(size, 2)))
+}
+
+/// Geometry of the small world of `watts_strogatz_fn`, with the sites where the torus put them
+pub fn watts_strogatz_geometry(size: usize, t1: f32, t2: f32, rewire_prob: f32, seed: u64) -> Geometry {
+    Geometry::from_fn(size*size, &watts_strogatz_fn(size, t1, t2, rewire_prob, seed), Boundary::Periodic(vec![size as f32; 2]),
+        &format!("watts_strogatz_fn {} t1={} t2={} rewire_prob={} seed={}", size, t1, t2, rewire_prob, seed))
+        .with_positions(grid_positions(size, 2))
+}
+
+/// `geometry` after `site_dilute_fn`, with the positions of the kept sites
+pub fn site_diluted_geometry(geometry: &Geometry, occupation: f32, seed: u64) -> Geometry {
+    let (adjacency, couplings) = (geometry.adjacency(), geometry.couplings());
+    let (kept, func) = site_dilute_fn(geometry.num_sites(), &adjacency.neighbor_fn(&couplings), occupation, seed);
+    let diluted = Geometry::from_fn(kept.len(), &func, geometry.boundary.clone(),
+        &format!("site dilution occupation={} seed={} of {}", occupation, seed, geometry.provenance));
+    if geometry.dimension() == 0 { return diluted; }
+    diluted.with_positions(kept.iter().map(|site| geometry.positions[*site].clone()).collect())
+}
+
+/// `geometry` after `bond_dilute_fn`, with all of its sites
+pub fn bond_diluted_geometry(geometry: &Geometry, occupation: f32, seed: u64) -> Geometry {
+    let (adjacency, couplings) = (geometry.adjacency(), geometry.couplings());
+    let func = bond_dilute_fn(geometry.num_sites(), &adjacency.neighbor_fn(&couplings), occupation, seed);
+    Geometry::from_fn(geometry.num_sites(), &func, geometry.boundary.clone(),
+        &format!("bond dilution occupation={} seed={} of {}", occupation, seed, geometry.provenance))
+        .with_positions(geometry.positions.clone())
+}
+
+/// Bonds of the Penrose patch stored in `data/penrose-{level}.npy`, all of kind 0
+pub fn load_penrose_adjacency(level: usize) -> Result<Adjacency, LoadError> {
+    Adjacency::from_npy(&[format!("data/penrose-{}.npy", level)])
+}
+
+/// Geometry file contents for the Penrose patch
+pub fn penrose_geometry(level: usize) -> Result<Geometry, LoadError> {
+    Ok(Geometry::from_adjacency(&load_penrose_adjacency(level)?, vec![("edge".to_owned(), 1.)], Boundary::Open,
+        &format!("penrose level {}", level)))
+}
+
+#[allow(clippy::type_complexity)]
+pub fn load_penrose(level: usize) -> Result<(usize, impl Fn(usize) ->Vec<(usize, f32)>), LoadError> {
+    let adjacency = load_penrose_adjacency(level)?;
+    Ok((
+        adjacency.num_sites(),
+        move |site: usize| { adjacency.neighbor_fn(&[1.0])(site) }
+    ))
+}
+
+/// Bonds of the einstein patch stored in `data/einstein-{name}-*.npy`. The short, medium and long
+/// bonds have kinds 0, 1 and 2.
+pub fn load_einstein_adjacency(name: &str) -> Result<Adjacency, LoadError> {
+    Adjacency::from_npy(&[
+        format!("data/einstein-{}-short.npy", name),
+        format!("data/einstein-{}-medium.npy", name),
+        format!("data/einstein-{}-long.npy", name),
+    ])
+}
+
+/// Geometry file contents for the einstein patch, with the couplings of `einstein_couplings`
+pub fn einstein_geometry(name: &str, t1: f32, t2: f32) -> Result<Geometry, LoadError> {
+    let couplings = einstein_couplings(t1, t2);
+    let kinds = ["short", "medium", "long"].iter().zip(couplings)
+        .map(|(label, j)| (label.to_string(), j)).collect();
+    Ok(Geometry::from_adjacency(&load_einstein_adjacency(name)?, kinds, Boundary::Open,
+        &format!("einstein {} t1={} t2={}", name, t1, t2)))
+}
+
+/// Horizontal coupling `t1` of the anisotropy `eta`, which runs from -1 to 1 with `t1 = t2 = 1` at 0
+pub fn get_t1_from_eta(eta: f32) -> f32 {
+    ((-eta + 1.) / 4. * std::f32::consts::PI).tan()
+}
+
+/// Vertical coupling `t2` of the anisotropy `eta`
+pub fn get_t2_from_eta(eta: f32) -> f32 {
+    ((eta + 1.) / 4. * std::f32::consts::PI).tan()
+}
+
+/// Couplings of the short, medium and long einstein bonds
+pub fn einstein_couplings(t1: f32, t2: f32) -> [f32; 3] {
+    [1.0/(t1*t1), 1./(t2*t2), 0.25/(t1*t1)]
+}
+
+#[allow(clippy::type_complexity)]
+pub fn load_einstein(name: &str, t1: f32, t2: f32) -> Result<(usize, impl Fn(usize) ->Vec<(usize, f32)>), LoadError> {
+    let adjacency = load_einstein_adjacency(name)?;
+    let couplings = einstein_couplings(t1, t2);
+    Ok((
+        adjacency.num_sites(),
+        move |site: usize| { adjacency.neighbor_fn(&couplings)(site) }
+    ))
+}
+
+/// Simple cubic torus with `row_size^3` sites and unit couplings.
+pub fn cubic_fn(row_size: usize) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    move |site: usize| {
+        let (x, y, z) = (site % row_size, (site / row_size) % row_size, site / (row_size * row_size));
+        let index = |x: usize, y: usize, z: usize| { x + row_size * (y + row_size * z) };
+        vec![
+            (index((x + row_size - 1) % row_size, y, z), 1.),
+            (index((x + 1) % row_size, y, z), 1.),
+            (index(x, (y + row_size - 1) % row_size, z), 1.),
+            (index(x, (y + 1) % row_size, z), 1.),
+            (index(x, y, (z + row_size - 1) % row_size), 1.),
+            (index(x, y, (z + 1) % row_size), 1.),
+        ]
+    }
+}
+
+/// Body-centered cubic torus with `2 * row_size^3` sites. The first `row_size^3` sites are the cube
+/// corners and the rest are the body centers. Nearest neighbors have unit coupling, and second
+/// neighbors (along the cube axes) have coupling `j2`, which is skipped entirely when zero.
+pub fn bcc_fn(row_size: usize, j2: f32) -> impl Fn(usize) -> Vec<(usize, f32)> {
+    move |site: usize| {
+        let cell_count = row_size * row_size * row_size;
+        let sublattice = site / cell_count;
+        let cell = site % cell_count;
+        let (x, y, z) = (cell % row_size, (cell / row_size) % row_size, cell / (row_size * row_size));
+        let index = |sublattice: usize, x: usize, y: usize, z: usize| {
+            sublattice * cell_count + x + row_size * (y + row_size * z)
+        };
+        // Corners see the body centers of the cells below them, body centers see the corners above.
+        let shift = |a: usize, d: usize| {
+            if sublattice == 0 { (a + row_size - d) % row_size } else { (a + d) % row_size }
+        };
+
+        let mut neighbors = Vec::new();
+        for d in 0..8 {
+            let (dx, dy, dz) = (d & 1, (d >> 1) & 1, (d >> 2) & 1);
+            neighbors.push((index(1 - sublattice, shift(x, dx), shift(y, dy), shift(z, dz)), 1.));
+        }
+        if j2 != 0. {
+            neighbors.push((index(sublattice, (x + row_size - 1) % row_size, y, z), j2));
+            neighbors.push((index(sublattice, (x + 1) % row_size, y, z), j2));
+            neighbors.push((index(sublattice, x, (y + row_size - 1) % row_size, z), j2));
+            neighbors.push((index(sublattice, x, (y + 1) % row_size, z), j2));
+            neighbors.push((index(sublattice, x, y, (z + row_size - 1) % row_size), j2));
+            neighbors.push((index(sublattice, x, y, (z + 1) % row_size), j2));
+        }
+        neighbors
+    }
+}
+
+/// Vertex graph of the Ammann rhombohedral (icosahedral) tiling within `radius` of the origin, with
+/// every edge given coupling `j`. Edges have unit length.
+pub fn icosahedral_fn(radius: f32, j: f32) -> (usize, impl Fn(usize) -> Vec<(usize, f32)>) {
+    CutProject::icosahedral().generate(radius, BondRule::Hyperlattice).neighbor_fn(j)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `func` is a simple graph with symmetric bonds and returns its degrees
+    fn simple_degrees(num_sites: usize, func: &impl Fn(usize) -> Vec<(usize, f32)>) -> Vec<usize> {
+        (0..num_sites).map(|site| {
+            let neighbors = func(site);
+            let distinct = neighbors.iter().map(|n| n.0).collect::<HashSet<_>>();
+            assert_eq!(distinct.len(), neighbors.len(), "site {} has a repeated bond", site);
+            assert!(!distinct.contains(&site), "site {} has a self-loop", site);
+            for (neighbor, _) in &neighbors {
+                assert!(func(*neighbor).iter().any(|n| n.0 == site), "bond {}-{} is one-way", site, neighbor);
             }
-            neighbors
+            neighbors.len()
+        }).collect()
+    }
+
+    #[test]
+    fn random_regular_graph_of_degree_eight() {
+        let func = random_regular_fn(200, 8, 1).unwrap();
+        assert!(simple_degrees(200, &func).iter().all(|d| *d == 8));
+    }
+
+    #[test]
+    fn dense_random_regular_graph() {
+        let func = random_regular_fn(20, 15, 2).unwrap();
+        assert!(simple_degrees(20, &func).iter().all(|d| *d == 15));
+    }
+
+    #[test]
+    fn degree_matched_keeps_the_degrees_of_bcc() {
+        let (num_sites, bcc) = (2 * 4 * 4 * 4, bcc_fn(4, 0.5));
+        let func = degree_matched_fn(num_sites, &bcc, 3).unwrap();
+        let mut expected = (0..num_sites).map(|site| bcc(site).len()).collect::<Vec<_>>();
+        let mut degrees = simple_degrees(num_sites, &func);
+        expected.sort_unstable();
+        degrees.sort_unstable();
+        assert_eq!(degrees, expected);
+    }
+
+    #[test]
+    fn random_regular_graphs_differ_by_seed() {
+        let (a, b) = (random_regular_fn(50, 8, 1).unwrap(), random_regular_fn(50, 8, 2).unwrap());
+        assert!((0..50).any(|site| a(site) != b(site)));
+    }
+
+    #[test]
+    fn periodic_fibonacci_chain_is_a_ring() {
+        let func = fibonacci_chain_fn(8, 1., 2., true).unwrap();
+        assert!(simple_degrees(8, &func).iter().all(|d| *d == 2));
+        assert_eq!(func(7), vec![(6, 2.), (0, 1.)]);
+    }
+
+    #[test]
+    fn periodic_fibonacci_grid_is_a_torus() {
+        let func = fibonacci_grid_fn(5, 1., 2., true).unwrap();
+        assert!(simple_degrees(25, &func).iter().all(|d| *d == 4));
+    }
+
+    #[test]
+    fn fibonacci_lengths_are_checked() {
+        for length in [0, 1, 2, 4, 6, 7] {
+            assert!(fibonacci_chain_fn(length, 1., 2., true).is_err(), "periodic length {}", length);
+            assert!(fibonacci_grid_fn(length, 1., 2., true).is_err(), "periodic size {}", length);
         }
-    )
-}
\ No newline at end of file
+        assert!(fibonacci_chain_fn(0, 1., 2., false).is_err());
+        assert!(fibonacci_chain_fn(1, 1., 2., false).unwrap()(0).is_empty());
+        assert_eq!(simple_degrees(7, &fibonacci_chain_fn(7, 1., 2., false).unwrap()), vec![1, 2, 2, 2, 2, 2, 1]);
+    }
+
+    #[test]
+    fn impossible_degrees_are_rejected() {
+        assert!(random_regular_fn(5, 3, 0).is_err());
+        assert!(random_regular_fn(4, 4, 0).is_err());
+        assert!(!is_graphical(&[3, 3, 1, 1]));
+        assert!(is_graphical(&[3, 3, 2, 2, 2]));
+    }
+}
@@ -0,0 +1,283 @@
+//! Self-describing lattice files. The format is plain text:
+//!
+//! ```text
+//! quasing-lattice 1
+//! provenance <free text to the end of the line>
+//! boundary open | periodic <length> ... <length>
+//! sites <number of sites> <dimension>
+//! kinds <number of bond kinds>
+//! <label> <default coupling>          (one line per kind)
+//! positions                           (only if the dimension is nonzero)
+//! <x> ... <x>                         (one line per site)
+//! bonds <number of bonds>
+//! <site> <site> <kind>                (one line per bond, each bond listed once)
+//! ```
+
+use std::{fs::File, io::{Write, BufRead, BufReader}};
+
+use crate::adjacency::Adjacency;
+use crate::validate::{validate, Expect, LoadError};
+
+pub const FORMAT_VERSION: usize = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Boundary {
+    Open,
+    /// Periodic with the given box lengths along each axis
+    Periodic(Vec<f32>),
+}
+
+/// A lattice with everything needed to simulate and draw it
+pub struct Geometry {
+    pub provenance: String,
+    pub boundary: Boundary,
+    /// Site coordinates. Empty if the lattice has no embedding.
+    pub positions: Vec<Vec<f32>>,
+    /// Label and default coupling of each bond kind
+    pub kinds: Vec<(String, f32)>,
+    /// `(site, site, kind)`, each bond listed once
+    pub bonds: Vec<(usize, usize, usize)>,
+    num_sites: usize,
+}
+
+impl Geometry {
+    pub fn new(num_sites: usize, bonds: Vec<(usize, usize, usize)>, kinds: Vec<(String, f32)>,
+        boundary: Boundary, provenance: &str) -> Self {
+        Self {
+            provenance: provenance.to_owned(),
+            boundary,
+            positions: Vec::new(),
+            kinds,
+            bonds,
+            num_sites,
+        }
+    }
+
+    /// Record any neighbor function. Each distinct coupling becomes its own bond kind, labeled by
+    /// its order of appearance.
+    pub fn from_fn(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>,
+        boundary: Boundary, provenance: &str) -> Self {
+        let mut kinds: Vec<(String, f32)> = Vec::new();
+        let mut bonds = Vec::new();
+        for site in 0..num_sites {
+            for (neighbor, strength) in neighbor_func(site) {
+                if site >= neighbor { continue; }
+                let kind = match kinds.iter().position(|(_, j)| *j == strength) {
+                    Some(kind) => kind,
+                    None => {
+                        kinds.push((format!("j{}", kinds.len()), strength));
+                        kinds.len() - 1
+                    }
+                };
+                bonds.push((site, neighbor, kind));
+            }
+        }
+        Self::new(num_sites, bonds, kinds, boundary, provenance)
+    }
+
+    /// Record a shared adjacency, labeling its bond kinds with `kinds`
+    pub fn from_adjacency(adjacency: &Adjacency, kinds: Vec<(String, f32)>,
+        boundary: Boundary, provenance: &str) -> Self {
+        let mut bonds = Vec::new();
+        for site in 0..adjacency.num_sites() {
+            for (neighbor, kind) in adjacency.neighbors(site) {
+                if site < neighbor {
+                    bonds.push((site, neighbor, kind));
+                }
+            }
+        }
+        Self::new(adjacency.num_sites(), bonds, kinds, boundary, provenance)
+    }
+
+    pub fn with_positions(mut self, positions: Vec<Vec<f32>>) -> Self {
+        assert_eq!(positions.len(), self.num_sites, "Every site needs a position");
+        self.positions = positions;
+        self
+    }
+
+    pub fn num_sites(&self) -> usize {
+        self.num_sites
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.positions.first().map_or(0, |p| p.len())
+    }
+
+    /// Default coupling of each bond kind, for `Lattice::from_adjacency`
+    pub fn couplings(&self) -> Vec<f32> {
+        self.kinds.iter().map(|(_, j)| *j).collect()
+    }
+
+    pub fn adjacency(&self) -> Adjacency {
+        Adjacency::from_bonds(self.num_sites, &self.bonds)
+    }
+
+    /// Write the geometry file read by `load`
+    pub fn save(&self, filename: &str) -> Result<(), LoadError> {
+        self.write(filename).map_err(|source| LoadError::Io { file: filename.to_owned(), source })
+    }
+
+    fn write(&self, filename: &str) -> std::io::Result<()> {
+        let mut f = File::create(filename)?;
+        writeln!(f, "quasing-lattice {}", FORMAT_VERSION)?;
+        writeln!(f, "provenance {}", self.provenance.replace('\n', " "))?;
+        match &self.boundary {
+            Boundary::Open => writeln!(f, "boundary open")?,
+            Boundary::Periodic(lengths) => {
+                write!(f, "boundary periodic")?;
+                for length in lengths {
+                    write!(f, " {}", length)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        writeln!(f, "sites {} {}", self.num_sites, self.dimension())?;
+        writeln!(f, "kinds {}", self.kinds.len())?;
+        for (label, coupling) in &self.kinds {
+            writeln!(f, "{} {}", label, coupling)?;
+        }
+        if self.dimension() > 0 {
+            writeln!(f, "positions")?;
+            for position in &self.positions {
+                let line = position.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ");
+                writeln!(f, "{}", line)?;
+            }
+        }
+        writeln!(f, "bonds {}", self.bonds.len())?;
+        for (a, b, kind) in &self.bonds {
+            writeln!(f, "{} {} {}", a, b, kind)?;
+        }
+        Ok(())
+    }
+
+    /// Read a geometry file. Errors carry the line where parsing failed, and the bonds must form a
+    /// simple, symmetric graph.
+    pub fn load(filename: &str) -> Result<Self, LoadError> {
+        let file = File::open(filename).map_err(|source| LoadError::Io { file: filename.to_owned(), source })?;
+        let mut lines = BufReader::new(file).lines().enumerate();
+        let error = |line: usize, message: String| {
+            LoadError::Parse { file: filename.to_owned(), line: Some(line + 1), message }
+        };
+        let mut next = || -> Result<(usize, String), LoadError> {
+            match lines.next() {
+                Some((i, Ok(line))) => Ok((i, line)),
+                Some((_, Err(source))) => Err(LoadError::Io { file: filename.to_owned(), source }),
+                None => Err(LoadError::Parse { file: filename.to_owned(), line: None, message: "unexpected end of file".to_owned() }),
+            }
+        };
+        fn parse<T: std::str::FromStr>(word: Option<&str>) -> Result<T, String> {
+            let word = word.ok_or("missing value")?;
+            word.parse().map_err(|_| format!("could not parse {:?}", word))
+        }
+        let keyword = |(i, line): &(usize, String), key: &str| -> Result<Vec<String>, LoadError> {
+            let mut words = line.split_whitespace();
+            if words.next() != Some(key) {
+                return Err(error(*i, format!("expected {:?}", key)));
+            }
+            Ok(words.map(|w| w.to_owned()).collect())
+        };
+
+        let header = next()?;
+        let version: usize = parse(keyword(&header, "quasing-lattice")?.first().map(|w| w.as_str())).map_err(|m| error(header.0, m))?;
+        if version > FORMAT_VERSION {
+            return Err(error(header.0, format!("version {} is newer than this code", version)));
+        }
+        let provenance = keyword(&next()?, "provenance")?.join(" ");
+        let boundary_line = next()?;
+        let boundary_words = keyword(&boundary_line, "boundary")?;
+        let boundary = match boundary_words.first().map(|w| w.as_str()) {
+            Some("open") => Boundary::Open,
+            Some("periodic") => Boundary::Periodic(boundary_words[1..].iter()
+                .map(|w| parse(Some(w))).collect::<Result<_, _>>().map_err(|m| error(boundary_line.0, m))?),
+            other => return Err(error(boundary_line.0, format!("unknown boundary {:?}", other))),
+        };
+        let sites_line = next()?;
+        let counts = keyword(&sites_line, "sites")?;
+        let num_sites: usize = parse(counts.first().map(|w| w.as_str())).map_err(|m| error(sites_line.0, m))?;
+        let dimension: usize = parse(counts.get(1).map(|w| w.as_str())).map_err(|m| error(sites_line.0, m))?;
+
+        let kinds_line = next()?;
+        let num_kinds: usize = parse(keyword(&kinds_line, "kinds")?.first().map(|w| w.as_str())).map_err(|m| error(kinds_line.0, m))?;
+        let mut kinds = Vec::with_capacity(num_kinds);
+        for _ in 0..num_kinds {
+            let (i, line) = next()?;
+            let (label, coupling) = line.trim().rsplit_once(' ').ok_or_else(|| error(i, "expected a label and a coupling".to_owned()))?;
+            kinds.push((label.to_owned(), parse(Some(coupling)).map_err(|m| error(i, m))?));
+        }
+
+        let mut positions = Vec::new();
+        if dimension > 0 {
+            keyword(&next()?, "positions")?;
+            for _ in 0..num_sites {
+                let (i, line) = next()?;
+                let position = line.split_whitespace().map(|w| parse(Some(w))).collect::<Result<Vec<f32>, _>>().map_err(|m| error(i, m))?;
+                if position.len() != dimension {
+                    return Err(error(i, format!("expected {} coordinates", dimension)));
+                }
+                positions.push(position);
+            }
+        }
+
+        let bonds_line = next()?;
+        let num_bonds: usize = parse(keyword(&bonds_line, "bonds")?.first().map(|w| w.as_str())).map_err(|m| error(bonds_line.0, m))?;
+        let mut bonds = Vec::with_capacity(num_bonds);
+        for _ in 0..num_bonds {
+            let (i, line) = next()?;
+            let words = line.split_whitespace().map(|w| parse(Some(w))).collect::<Result<Vec<usize>, _>>().map_err(|m| error(i, m))?;
+            if words.len() != 3 {
+                return Err(error(i, "expected two sites and a kind".to_owned()));
+            }
+            if words[0] >= num_sites || words[1] >= num_sites || words[2] >= num_kinds {
+                return Err(error(i, format!("bond {:?} refers to a missing site or kind", words)));
+            }
+            bonds.push((words[0], words[1], words[2]));
+        }
+
+        let geometry = Self { provenance, boundary, positions, kinds, bonds, num_sites };
+        let problems = validate(num_sites, &geometry.adjacency().neighbor_fn(&geometry.couplings()), &Expect::any());
+        if !problems.is_empty() {
+            return Err(LoadError::Invalid { file: filename.to_owned(), problems });
+        }
+        Ok(geometry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::funcs::{bcc_geometry, rect_geometry};
+
+    fn round_trip(geometry: &Geometry, name: &str) -> Geometry {
+        let filename = std::env::temp_dir().join(format!("quasing-{}-{}.lat", name, std::process::id()));
+        let filename = filename.to_str().unwrap();
+        geometry.save(filename).unwrap();
+        let loaded = Geometry::load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        loaded
+    }
+
+    fn assert_same(a: &Geometry, b: &Geometry) {
+        assert_eq!(a.provenance, b.provenance);
+        assert_eq!(a.boundary, b.boundary);
+        assert_eq!(a.num_sites(), b.num_sites());
+        assert_eq!(a.positions, b.positions);
+        assert_eq!(a.kinds, b.kinds);
+        assert_eq!(a.bonds, b.bonds);
+    }
+
+    #[test]
+    fn periodic_lattices_survive_a_round_trip() {
+        for (name, geometry) in [("rect", rect_geometry(4, 1., 0.5)), ("bcc", bcc_geometry(3, 0.25))] {
+            assert_same(&geometry, &round_trip(&geometry, name));
+        }
+    }
+
+    #[test]
+    fn open_lattice_without_positions_survives_a_round_trip() {
+        let kinds = vec![("short".to_owned(), 1.), ("long".to_owned(), -0.5)];
+        let geometry = Geometry::new(3, vec![(0, 1, 0), (1, 2, 1)], kinds, Boundary::Open, "path of three sites");
+        let loaded = round_trip(&geometry, "open");
+        assert_same(&geometry, &loaded);
+        assert_eq!(loaded.dimension(), 0);
+    }
+}
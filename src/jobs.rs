@@ -0,0 +1,26 @@
+//! A queue of independent tasks shared by worker threads. Workers take the next task as soon as
+//! they finish one, so slow tasks do not hold up the rest of a scan.
+
+use std::sync::Mutex;
+use std::thread;
+
+/// Runs `work(index, task)` for every task on `workers` threads and returns the results in task
+/// order. Tasks are handed out in order, but a task must not depend on which worker runs it or
+/// when, so it should take its seed from its index.
+pub fn run<T: Send, R: Send>(tasks: Vec<T>, workers: usize, work: impl Fn(usize, T) -> R + Sync) -> Vec<R> {
+    let num_tasks = tasks.len();
+    let queue = Mutex::new(tasks.into_iter().enumerate());
+    let results = Mutex::new((0..num_tasks).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..workers.min(num_tasks) {
+            scope.spawn(|| loop {
+                // The lock is released before the task runs
+                let next = queue.lock().unwrap().next();
+                let Some((index, task)) = next else { break };
+                let result = work(index, task);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|r| r.unwrap()).collect()
+}
@@ -1,127 +1,379 @@
 use std::fmt::Display;
 
-use crate::spin::{Spin, SmallVec};
-use rand::random;
+use crate::spin::Spin;
+use crate::sampler::Sampler;
+use crate::snapshot::{self, Snapshots};
+use crate::validate::LoadError;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-const CLUSTER_STACK_MAX_SIZE: usize = 256_000;
-const BURN_IN: usize = 128;
-const RUNS_PER_TRIAL: usize = 16;
+const NUM_BINS: usize = 20;
 
-/// Result from a complete run of a lattice
+/// Result from a complete run of a lattice. Errors are jackknife estimates over `NUM_BINS`
+/// consecutive blocks of measurements. Observables a model does not measure are NaN.
 pub struct Report {
     pub magnetization: f32,
+    pub magnetization_error: f32,
     pub susceptibility: f32,
+    pub susceptibility_error: f32,
+    /// Energy per site
+    pub energy: f32,
+    pub energy_error: f32,
+    /// Specific heat per site
+    pub specific_heat: f32,
+    pub specific_heat_error: f32,
+    /// Binder cumulant `1 - <m^4> / 3<m^2>^2`
+    pub binder: f32,
+    pub binder_error: f32,
+    /// Number of measurements averaged
+    pub num_measurements: usize,
 }
 
 impl Display for Report {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "Magnetization {}", self.magnetization)?;
-        writeln!(f, "Susceptibility {}", self.susceptibility)
+        writeln!(f, "Magnetization {} +/- {}", self.magnetization, self.magnetization_error)?;
+        writeln!(f, "Susceptibility {} +/- {}", self.susceptibility, self.susceptibility_error)?;
+        writeln!(f, "Energy {} +/- {}", self.energy, self.energy_error)?;
+        writeln!(f, "Specific heat {} +/- {}", self.specific_heat, self.specific_heat_error)?;
+        writeln!(f, "Binder cumulant {} +/- {}", self.binder, self.binder_error)
+    }
+}
+
+impl Report {
+    /// Computes every observable from the per-measurement magnetizations and energies (both per
+    /// site). `energies` may be empty if the model does not measure them.
+    pub fn from_measurements(magnetizations: &[f32], energies: &[f32], beta: f32, num_sites: usize) -> Self {
+        let n = num_sites as f64;
+        let beta = beta as f64;
+        // Sums of m, m^2, m^4, e, e^2 over each block
+        let num_bins = NUM_BINS.min(magnetizations.len()).max(1);
+        let mut bins = vec![[0f64; 5]; num_bins];
+        let mut counts = vec![0f64; num_bins];
+        for (i, m) in magnetizations.iter().enumerate() {
+            let bin = i * num_bins / magnetizations.len();
+            let m = *m as f64;
+            let e = energies.get(i).map_or(f64::NAN, |e| *e as f64);
+            for (total, x) in bins[bin].iter_mut().zip([m, m * m, m * m * m * m, e, e * e]) {
+                *total += x;
+            }
+            counts[bin] += 1.;
+        }
+
+        let observables = |moments: [f64; 5]| {
+            let [m, m2, m4, e, e2] = moments;
+            [m, beta * n * (m2 - m * m), e, beta * beta * n * (e2 - e * e), 1. - m4 / (3. * m2 * m2)]
+        };
+        let mean_without = |skip: Option<usize>| {
+            let mut total = [0.; 5];
+            let mut count = 0.;
+            for (bin, (sums, c)) in bins.iter().zip(&counts).enumerate() {
+                if Some(bin) == skip { continue; }
+                for (t, x) in total.iter_mut().zip(sums) {
+                    *t += x;
+                }
+                count += c;
+            }
+            total.map(|t| t / count)
+        };
+
+        let full = observables(mean_without(None));
+        let mut errors = [0.; 5];
+        if num_bins > 1 {
+            for bin in 0..num_bins {
+                let jackknife = observables(mean_without(Some(bin)));
+                for (error, (x, y)) in errors.iter_mut().zip(jackknife.iter().zip(&full)) {
+                    *error += (x - y) * (x - y);
+                }
+            }
+            errors = errors.map(|e| (e * (num_bins - 1) as f64 / num_bins as f64).sqrt());
+        }
+
+        Report {
+            magnetization: full[0] as f32,
+            magnetization_error: errors[0] as f32,
+            susceptibility: full[1] as f32,
+            susceptibility_error: errors[1] as f32,
+            energy: full[2] as f32,
+            energy_error: errors[2] as f32,
+            specific_heat: full[3] as f32,
+            specific_heat_error: errors[3] as f32,
+            binder: full[4] as f32,
+            binder_error: errors[4] as f32,
+            num_measurements: magnetizations.len(),
+        }
+    }
+}
+
+/// Progress of `run_from` at one beta
+pub struct RunState {
+    pub beta: f32,
+    pub burned_in: bool,
+    pub magnetizations: Vec<f32>,
+    pub energies: Vec<f32>,
+}
+
+impl RunState {
+    pub fn new(beta: f32) -> Self {
+        Self { beta, burned_in: false, magnetizations: Vec::new(), energies: Vec::new() }
     }
 }
 
 pub struct Lattice<S: Spin> {
     data: Vec<S>,
-    neighbors: Vec<SmallVec<(usize, f32)>>,
-    cluster_stack: [usize; CLUSTER_STACK_MAX_SIZE],
+    /// Neighbors of every site stored contiguously. Those of site `i` are at
+    /// `neighbor_offsets[i]..neighbor_offsets[i+1]`.
+    neighbors: Vec<(usize, f32)>,
+    neighbor_offsets: Vec<usize>,
+    /// Sites waiting to grow the cluster. Each site joins a cluster once, so it never holds more
+    /// than `num_sites`.
+    cluster_stack: Vec<usize>,
+    cluster_sites: Vec<usize>,
+    snapshots: Option<Snapshots>,
+    series: Option<String>,
+    rng: ChaCha8Rng,
+    /// Clusters flipped, and sites in them, since the lattice was made
+    num_clusters: u64,
+    num_cluster_sites: u64,
 }
 
 impl<S: Spin> Lattice<S> {
-    /// Create a new lattice from a function that generates neighbors.
-    pub fn new(num_sites: usize, neighbor_func: &impl Fn(usize) -> SmallVec<(usize, f32)>) -> Self {
+    /// Components of every spin, `S::num_components()` per site
+    pub fn components(&self) -> Vec<f32> {
+        self.data.iter().flat_map(|s| s.components()).collect()
+    }
+
+    /// Sets the spins from `S::num_components()` components per site
+    pub fn set_components(&mut self, components: &[f32]) {
+        assert_eq!(components.len(), self.data.len() * S::num_components(), "Every site needs all its components");
+        for (item, components) in self.data.iter_mut().zip(components.chunks(S::num_components())) {
+            *item = S::from_components(components);
+        }
+    }
+
+    /// Writes the spins to `{name}.npy` as an `f32` array of shape `(num_sites, S::num_components())`
+    pub fn save_snapshot(&self, name: &str) -> Result<(), LoadError> {
+        snapshot::write_f32(&format!("{}.npy", name), &[self.data.len(), S::num_components()], &self.components())
+    }
+
+    /// Flips `count` clusters and returns the average cluster size as a fraction of the lattice. 
+    fn evolve(&mut self, beta: f32, count: usize) -> f32 {
+        let mut cluster_size = 0;
+        for _ in 0..count {
+            // 1. Choose random site
+            let start_index = self.rng.gen_range(0..self.data.len());
+            let vec = S::random_vec(&mut self.rng);
+            let mut marked_sites = vec![false; self.data.len()];
+            cluster_size += self.grow_cluster(start_index, beta, &vec, &mut marked_sites);
+        }
+        self.num_clusters += count as u64;
+        self.num_cluster_sites += (cluster_size + count) as u64;
+        cluster_size as f32 / count as f32 / self.data.len() as f32
+    }
+
+    /// Flips exactly one cluster grown from `start_index` and marks its sites in `marked_sites`. The
+    /// sites added after the first are written to the start of `cluster_sites`, and their number is
+    /// returned. This code has been optimized to make it branchless. 
+    fn grow_cluster(&mut self, start_index: usize, beta: f32, vec: &S::V, marked_sites: &mut [bool]) -> usize {
+        let mut cluster_size = 0;
+
+        // 2. Mark, and flip.
+        let mut stack_pointer: i32 = 0;
+        self.cluster_stack[0] = start_index;
+        self.data[start_index].flip(vec, true);
+        marked_sites[start_index] = true;
+
+        while stack_pointer >= 0 {
+            // 3. Iterate through neighbors and flip
+            let my_index = self.cluster_stack[stack_pointer as usize];
+            let mut new_stack_pointer = stack_pointer - 1;
+            for (neighbor, strength) in self.neighbors[self.neighbor_offsets[my_index]..self.neighbor_offsets[my_index + 1]].iter() {
+                let prob = 1. - (0.0f32.min(2. * beta * strength
+                    * self.data[my_index].dot(vec)
+                    * self.data[*neighbor].dot(vec))
+                ).exp();
+                
+                let success = !marked_sites[*neighbor] && (self.rng.gen::<f32>() < prob);
+                self.cluster_stack[(new_stack_pointer + 1) as usize] = *neighbor;
+                self.cluster_sites[cluster_size] = *neighbor;
+                self.data[*neighbor].flip(vec, success);
+                new_stack_pointer += success as i32;
+                marked_sites[*neighbor] = marked_sites[*neighbor] || success;
+                cluster_size += success as usize;
+            }
+            stack_pointer = new_stack_pointer;
+        }
+        cluster_size
+    }
+
+    /// Flips one cluster grown from a random site, as `evolve` does, and returns its sites
+    pub fn wolff_cluster(&mut self, beta: f32) -> Vec<usize> {
+        let start_index = self.rng.gen_range(0..self.data.len());
+        let vec = S::random_vec(&mut self.rng);
+        let mut marked_sites = vec![false; self.data.len()];
+        let size = self.grow_cluster(start_index, beta, &vec, &mut marked_sites);
+        let mut cluster = Vec::with_capacity(size + 1);
+        cluster.push(start_index);
+        cluster.extend_from_slice(&self.cluster_sites[..size]);
+        cluster
+    }
+
+    pub fn spins(&self) -> &[S] {
+        &self.data
+    }
+
+    /// Sets every spin to `S::up()`
+    pub fn polarize(&mut self) {
+        for item in self.data.iter_mut() {
+            *item = S::up();
+        }
+    }
+
+    /// Splits the lattice into its connected components by growing clusters from a polarized state
+    /// at infinite beta, where every bond is accepted. Leaves the lattice polarized.
+    pub fn clusters(&mut self) -> Vec<Vec<usize>> {
+        self.polarize();
+        let mut marked_sites = vec![false; self.data.len()];
+        let mut clusters = Vec::new();
+        for start_index in 0..self.data.len() {
+            if marked_sites[start_index] { continue; }
+            let vec = S::random_vec(&mut self.rng);
+            let size = self.grow_cluster(start_index, f32::INFINITY, &vec, &mut marked_sites);
+            let mut cluster = Vec::with_capacity(size + 1);
+            cluster.push(start_index);
+            cluster.extend_from_slice(&self.cluster_sites[..size]);
+            clusters.push(cluster);
+        }
+        self.polarize();
+        clusters
+    }
+}
+
+impl<S: Spin> Sampler for Lattice<S> {
+    const BURN_IN: usize = 128;
+    const RUNS_PER_TRIAL: usize = 16;
+
+    fn new(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>) -> Self {
         let mut data = Vec::with_capacity(num_sites);
-        let mut neighbors = Vec::with_capacity(num_sites);
+        let mut neighbors = Vec::new();
+        let mut neighbor_offsets = Vec::with_capacity(num_sites + 1);
+        neighbor_offsets.push(0);
+        let mut rng = ChaCha8Rng::from_entropy();
         for site_index in 0..num_sites {
-            data.push(S::start());
-            neighbors.push(neighbor_func(site_index));
+            data.push(S::start(&mut rng));
+            neighbors.extend(neighbor_func(site_index));
+            neighbor_offsets.push(neighbors.len());
         }
 
         Self {
             data,
             neighbors,
-            cluster_stack: [0; CLUSTER_STACK_MAX_SIZE],
+            neighbor_offsets,
+            cluster_stack: vec![0; num_sites],
+            cluster_sites: vec![0; num_sites],
+            snapshots: None,
+            series: None,
+            rng,
+            num_clusters: 0,
+            num_cluster_sites: 0,
         }
     }
 
-    /// Zero out the data set
-    pub fn zero(&mut self) {
+    fn model() -> String {
+        S::name()
+    }
+
+    fn rng(&self) -> &ChaCha8Rng {
+        &self.rng
+    }
+
+    fn set_rng(&mut self, rng: ChaCha8Rng) {
+        self.rng = rng;
+    }
+
+    fn num_sites(&self) -> usize {
+        self.data.len()
+    }
+
+    fn cluster_counts(&self) -> (u64, u64) {
+        (self.num_clusters, self.num_cluster_sites)
+    }
+
+    fn zero(&mut self) {
         for item in self.data.iter_mut() {
-            *item = S::start();
+            *item = S::start(&mut self.rng);
         }
     }
 
-    /// Run cluster flips and computes the average magnetization and susceptibility every `RUNS_PER_TRIAL` iterations. Returns these as the data set after `n_trials` flips have elapsed, or until `checkpoint.0` flips have elapsed AND magnetization is over `checkpoint.1`. The checkpoint is a runtime-improvement feature. To turn it off, set `checkpoint.1` greater than 1.
-    pub fn run(&mut self, beta: f32, n_trials: usize, checkpoint: (usize, f32)) -> Report {
-        let mut num_avgs = n_trials - BURN_IN;
+    fn step(&mut self, beta: f32, count: usize) -> f32 {
+        self.evolve(beta, count)
+    }
 
-        self.evolve(beta, RUNS_PER_TRIAL * BURN_IN);
-        let mut avg_mag = 0.0;
-        let mut avg_mag2 = 0.0;
-        for trial_index in BURN_IN..n_trials {
-            self.evolve(beta, RUNS_PER_TRIAL);
-            let mag = self.magnetization();
-            avg_mag += mag;
-            avg_mag2 += mag * mag;
+    fn set_series(&mut self, prefix: Option<&str>) {
+        self.series = prefix.map(|p| p.to_owned());
+    }
 
-            if trial_index == checkpoint.0 {
-                if avg_mag / (trial_index - BURN_IN + 1) as f32 > checkpoint.1 {
-                    num_avgs = trial_index - BURN_IN + 1;
-                    break;
-                }
-            }
-        }
-        avg_mag /= num_avgs as f32;
-        avg_mag2 /= num_avgs as f32;
-        let sus = (avg_mag2 - avg_mag*avg_mag) * self.data.len() as f32;
-        Report {
-            magnetization: avg_mag,
-            susceptibility: sus * beta,
+    fn series(&self) -> Option<&str> {
+        self.series.as_deref()
+    }
+
+    fn maybe_snapshot(&self, beta: f32, measurement: usize, last: bool) -> Result<(), LoadError> {
+        match &self.snapshots {
+            Some(snapshots) if snapshots.wants(beta, measurement, last) =>
+                self.save_snapshot(&snapshots.filename(beta, measurement)),
+            _ => Ok(()),
         }
     }
 
-    /// Flips exactly one cluster. This code has been optimized to make it branchless. 
-    fn evolve(&mut self, beta: f32, count: usize) -> f32 {
-        let mut cluster_size = 0;
-        for _ in 0..count {
-            // 1. Choose random site
-            let start_index = random::<usize>() % self.data.len();
-            let vec = S::random_vec();
+    fn set_snapshots(&mut self, snapshots: Option<Snapshots>) {
+        self.snapshots = snapshots;
+    }
 
-            // 2. Mark, and flip.
-            let mut stack_pointer: i32 = 0;
-            self.cluster_stack[0] = start_index;
-            self.data[start_index].flip(&vec, true);
-            let mut marked_sites = vec![false; self.data.len()];
+    /// The snapshot must have one row per site and one column per spin component
+    fn load_snapshot(&mut self, name: &str) -> Result<(), LoadError> {
+        let filename = format!("{}.npy", name);
+        let (shape, data) = snapshot::read_f32(&filename)?;
+        if shape != [self.data.len(), S::num_components()] {
+            return Err(LoadError::Parse { file: filename, line: None,
+                message: format!("shape {:?} does not match {} sites with {} components", shape, self.data.len(), S::num_components()) });
+        }
+        self.set_components(&data);
+        Ok(())
+    }
 
-            while stack_pointer >= 0 {
-                // 3. Iterate through neighbors and flip
-                let my_index = self.cluster_stack[stack_pointer as usize];
-                let mut new_stack_pointer = stack_pointer - 1;
-                for (neighbor, strength) in self.neighbors[my_index].iter_mut() {
-                    let prob = 1. - (0.0f32.min(2. * beta * strength
-                        * self.data[my_index].dot(&vec)
-                        * self.data[*neighbor].dot(&vec))
-                    ).exp();
-                    
-                    let success = !marked_sites[*neighbor] && (random::<f32>() < prob);
-                    self.cluster_stack[(new_stack_pointer + 1) as usize] = *neighbor;
-                    self.data[*neighbor].flip(&vec, success);
-                    new_stack_pointer += success as i32;
-                    marked_sites[*neighbor] = marked_sites[*neighbor] || success;
-                    cluster_size += success as usize;
-                }
-                stack_pointer = new_stack_pointer;
-            }
+    fn magnetization_vector(&self) -> Vec<f32> {
+        let mut tot = S::zero();
+        for item in &self.data {
+            tot += item;
         }
-        cluster_size as f32 / count as f32 / self.data.len() as f32
+        tot.components().iter().map(|x| x / self.data.len() as f32).collect()
     }
 
-    /// Computes the magnetization of the crystal
-    pub fn magnetization(&self) -> f32 {
+    fn magnetization(&self) -> f32 {
         let mut tot = S::zero();
         for item in &self.data {
             tot += item;
         }
         tot.norm() / self.data.len() as f32
     }
-}
\ No newline at end of file
+
+    /// `-sum J s_i . s_j / N` over bonds
+    fn energy(&self) -> f32 {
+        let mut tot = 0.;
+        for (site, item) in self.data.iter().enumerate() {
+            for (neighbor, strength) in &self.neighbors[self.neighbor_offsets[site]..self.neighbor_offsets[site + 1]] {
+                tot -= strength * item.coupling(&self.data[*neighbor]);
+            }
+        }
+        tot / 2. / self.data.len() as f32
+    }
+
+    fn configuration(&self) -> Vec<Vec<f32>> {
+        self.data.iter().map(|s| s.components()).collect()
+    }
+
+    fn set_configuration(&mut self, configuration: &[Vec<f32>]) {
+        assert!(configuration.len() == self.data.len() && configuration.iter().all(|c| c.len() == S::num_components()),
+            "Every site needs all {} components of its spin", S::num_components());
+        self.set_components(&configuration.concat());
+    }
+}
@@ -0,0 +1,48 @@
+//! Wolff cluster Monte Carlo of classical spin models and the transverse-field Ising model on
+//! periodic and quasicrystalline lattices.
+//!
+//! A lattice is built from a `Geometry`, such as those of `funcs::rect_geometry` or
+//! `project::CutProject`, or straight from a neighbor function with `Lattice::new`. The classical
+//! `Lattice` and the quantum `QLattice` are both a `Sampler`, which the drivers run over a range of
+//! temperatures, returning the observables at each as `Data`:
+//!
+//! ```text
+//! let mut lattice = Lattice::<Ising>::from_geometry(&funcs::rect_geometry(32, 1., 1.));
+//! lattice.seed(1);
+//! let data = drivers::one_pass(&mut lattice, "rect", &drivers::linspace(0.3, 0.6, 20), 10_000, None, None)?;
+//! ```
+//!
+//! The commands of the `quasing-model` binary are here too, taking their settings from
+//! `options::Options`: `drivers::one_sweep`, `scaling::finite_size`, `phase::phase_diagram` and
+//! the rest write their outputs and return a `drivers::RunError` when they cannot.
+
+pub mod lattice;
+pub mod quantum;
+pub mod sampler;
+pub mod spin;
+pub mod funcs;
+pub mod adjacency;
+pub mod geometry;
+pub mod validate;
+pub mod project;
+pub mod substitute;
+pub mod percolation;
+pub mod output;
+pub mod snapshot;
+pub mod render;
+pub mod checkpoint;
+pub mod jobs;
+pub mod analysis;
+pub mod scaling;
+pub mod phase;
+pub mod log;
+pub mod config;
+pub mod options;
+pub mod drivers;
+
+pub use lattice::{Lattice, Report, RunState};
+pub use quantum::QLattice;
+pub use sampler::Sampler;
+pub use spin::{Spin, Ising, XY, Heisenberg};
+pub use geometry::{Geometry, Boundary};
+pub use output::{Data, Run};
@@ -0,0 +1,191 @@
+//! Progress messages. Each record has a level and an event name, and is printed to stderr if its
+//! level is enabled. With a log file, every record is also appended there as one JSON object per
+//! line, whatever its level:
+//!
+//! ```text
+//! {"time": 12.5, "level": "info", "event": "beta", "message": "...", "task": "rect-ising-0.5", ...}
+//! ```
+//!
+//! where `time` is in seconds since `init`.
+
+use std::{fs::{File, OpenOptions}, io::Write, sync::{Mutex, OnceLock}, time::Instant};
+
+use crate::output::json_string;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        }
+    }
+}
+
+struct Logger {
+    level: Level,
+    file: Option<Mutex<File>>,
+    start: Instant,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| Logger { level: Level::Info, file: None, start: Instant::now() })
+}
+
+/// Prints records up to `level`, and appends every record to `file` if given. Only the first call
+/// has an effect; records before it go to stderr at the info level.
+pub fn init(level: Level, file: Option<&str>) {
+    let file = file.map(|filename| {
+        let f = OpenOptions::new().create(true).append(true).open(filename)
+            .unwrap_or_else(|e| panic!("could not open log file {}: {}", filename, e));
+        Mutex::new(f)
+    });
+    let _ = LOGGER.set(Logger { level, file, start: Instant::now() });
+}
+
+/// Seconds since `init`
+pub fn elapsed() -> f64 {
+    logger().start.elapsed().as_secs_f64()
+}
+
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+/// One log record, built up with fields and written by `emit`
+pub struct Record {
+    level: Level,
+    event: &'static str,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Record {
+    pub fn new(level: Level, event: &'static str) -> Self {
+        Self { level, event, fields: Vec::new() }
+    }
+
+    pub fn text(mut self, name: &'static str, value: &str) -> Self {
+        self.fields.push((name, Value::Text(value.to_owned())));
+        self
+    }
+
+    pub fn number(mut self, name: &'static str, value: f64) -> Self {
+        self.fields.push((name, Value::Number(value)));
+        self
+    }
+
+    pub fn emit(self, message: &str) {
+        let logger = logger();
+        let time = logger.start.elapsed().as_secs_f64();
+        if self.level <= logger.level {
+            eprintln!("[{:>9.1}s] {:<5} {}", time, self.level.name().to_uppercase(), message);
+        }
+        if let Some(file) = &logger.file {
+            let mut line = format!("{{\"time\": {:.3}, \"level\": {}, \"event\": {}, \"message\": {}",
+                time, json_string(self.level.name()), json_string(self.event), json_string(message));
+            for (name, value) in &self.fields {
+                let value = match value {
+                    Value::Text(text) => json_string(text),
+                    Value::Number(x) if x.is_finite() => x.to_string(),
+                    Value::Number(_) => "null".to_owned(),
+                };
+                line += &format!(", {}: {}", json_string(name), value);
+            }
+            line += "}\n";
+            // Written in one call so that lines from different threads do not interleave
+            file.lock().unwrap().write_all(line.as_bytes()).unwrap();
+        }
+    }
+}
+
+pub fn error(message: &str) {
+    Record::new(Level::Error, "message").emit(message);
+}
+
+pub fn warn(message: &str) {
+    Record::new(Level::Warn, "message").emit(message);
+}
+
+pub fn info(message: &str) {
+    Record::new(Level::Info, "message").emit(message);
+}
+
+pub fn debug(message: &str) {
+    Record::new(Level::Debug, "message").emit(message);
+}
+
+/// Formats `seconds` as hours, minutes and seconds
+pub fn duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.).round() as u64;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+/// Timing of a task of `total` steps, such as the betas of a sweep, for progress records
+pub struct Progress {
+    task: String,
+    total: usize,
+    done: usize,
+    /// Steps finished before this process started, which do not count toward the rate
+    resumed: usize,
+    start: Instant,
+    last: Instant,
+}
+
+impl Progress {
+    /// Logs the start of `task`, of which `done` of `total` steps are already finished
+    pub fn start(task: &str, total: usize, done: usize) -> Self {
+        Record::new(Level::Info, "task_start").text("task", task).number("steps", total as f64).number("done", done as f64)
+            .emit(&format!("{}: started, {} of {} done", task, done, total));
+        let now = Instant::now();
+        Self { task: task.to_owned(), total, done, resumed: done, start: now, last: now }
+    }
+
+    /// Logs `record` and `message` for the step just finished, with the time it took, the time
+    /// since the start and the estimated time left
+    pub fn step(&mut self, record: Record, message: &str) {
+        self.done += 1;
+        let now = Instant::now();
+        let seconds = (now - self.last).as_secs_f64();
+        let elapsed = (now - self.start).as_secs_f64();
+        let remaining = elapsed / (self.done - self.resumed) as f64 * self.total.saturating_sub(self.done) as f64;
+        self.last = now;
+        record.text("task", &self.task)
+            .number("step", self.done as f64).number("steps", self.total as f64)
+            .number("seconds", seconds).number("elapsed", elapsed).number("remaining", remaining)
+            .emit(&format!("{}: {} ({}/{}) in {}, {} left", self.task, message, self.done, self.total,
+                duration(seconds), duration(remaining)));
+    }
+
+    /// Logs the end of the task
+    pub fn finish(self) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        Record::new(Level::Info, "task_finish").text("task", &self.task).number("elapsed", elapsed)
+            .emit(&format!("{}: finished in {}", self.task, duration(elapsed)));
+    }
+}
@@ -0,0 +1,420 @@
+//! Settings of every command, as read from the command line or a config file and written back
+//! out next to each output
+
+use crate::config;
+use crate::drivers::{linspace, reciprocal_linspace};
+use crate::funcs::{get_t1_from_eta, get_t2_from_eta};
+use crate::output::code_version;
+use crate::validate::LoadError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Sweep,
+    Search,
+    Refine,
+    Scaling,
+    Phase,
+    Percolation,
+    Dilution,
+    Export,
+    Render,
+    Help,
+}
+
+impl Command {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sweep" => Command::Sweep,
+            "search" => Command::Search,
+            "refine" => Command::Refine,
+            "scaling" => Command::Scaling,
+            "phase" | "eta-scan" => Command::Phase,
+            "percolation" => Command::Percolation,
+            "dilution" => Command::Dilution,
+            "export" => Command::Export,
+            "render" => Command::Render,
+            "help" => Command::Help,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Sweep => "sweep",
+            Command::Search => "search",
+            Command::Refine => "refine",
+            Command::Scaling => "scaling",
+            Command::Phase => "phase",
+            Command::Percolation => "percolation",
+            Command::Dilution => "dilution",
+            Command::Export => "export",
+            Command::Render => "render",
+            Command::Help => "help",
+        }
+    }
+}
+
+/// Settings shared by every command
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub lattice: String,
+    pub size: Option<usize>,
+    pub patch: String,
+    pub eta: f32,
+    /// Couplings that replace the ones mapped from eta
+    pub t1: Option<f32>,
+    pub t2: Option<f32>,
+    /// Option varied across a phase diagram, by config key or flag name, and its values
+    pub parameter: String,
+    pub from: f32,
+    pub to: f32,
+    pub steps: usize,
+    /// Values of the phase diagram parameter that replace the `steps` from `from` to `to`
+    pub values: Vec<f32>,
+    /// Estimator of Tc in a phase diagram
+    pub estimator: String,
+    /// Whether a dilution removes "site"s or "bond"s
+    pub dilution: String,
+    /// Dilutions averaged at every occupation
+    pub realizations: usize,
+    pub spin: String,
+    pub algorithm: Option<String>,
+    pub t_min: f32,
+    pub t_max: f32,
+    pub num_betas: usize,
+    /// Whether sweeps are evenly spaced in "temperature" or "beta"
+    pub spacing: String,
+    pub trials: usize,
+    pub seed: Option<u64>,
+    /// Snapshot the first temperature of a sweep starts from, instead of a fresh configuration
+    pub from_snapshot: Option<String>,
+    pub layers: usize,
+    pub per_layer: usize,
+    /// Observable whose peak a refine resolves
+    pub peak: String,
+    pub tolerance: f32,
+    pub max_betas: usize,
+    /// Sizes of a scaling run, or einstein patches
+    pub sizes: Vec<String>,
+    pub temperature: f32,
+    pub threads: usize,
+    pub output_dir: String,
+    pub name: Option<String>,
+    pub resume: bool,
+    pub series: bool,
+    /// Whether sweeps write a snapshot of the configuration at the end of every temperature
+    pub snapshots: bool,
+    pub log_level: String,
+    pub log_file: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            lattice: "rect".to_owned(),
+            size: None,
+            patch: "7k".to_owned(),
+            eta: 0.,
+            t1: None,
+            t2: None,
+            parameter: "eta".to_owned(),
+            from: -0.99,
+            to: 0.99,
+            steps: 40,
+            values: Vec::new(),
+            estimator: "steepest-descent".to_owned(),
+            dilution: "site".to_owned(),
+            realizations: 8,
+            spin: "ising".to_owned(),
+            algorithm: None,
+            t_min: 0.01,
+            t_max: 4.,
+            num_betas: 50,
+            spacing: "temperature".to_owned(),
+            trials: 10000,
+            seed: None,
+            from_snapshot: None,
+            layers: 4,
+            per_layer: 8,
+            peak: "susceptibility".to_owned(),
+            tolerance: 0.01,
+            max_betas: 100,
+            sizes: Vec::new(),
+            temperature: 2.,
+            threads: 8,
+            output_dir: "data/output".to_owned(),
+            name: None,
+            resume: false,
+            series: false,
+            snapshots: false,
+            log_level: "info".to_owned(),
+            log_file: None,
+        }
+    }
+}
+
+/// Command-line flags and the config file keys they match
+pub const KEYS: [(&str, &str); 39] = [
+    ("--lattice", "lattice.family"),
+    ("--size", "lattice.size"),
+    ("--patch", "lattice.patch"),
+    ("--eta", "couplings.eta"),
+    ("--t1", "couplings.t1"),
+    ("--t2", "couplings.t2"),
+    ("--parameter", "scan.parameter"),
+    ("--from", "scan.from"),
+    ("--to", "scan.to"),
+    ("--steps", "scan.steps"),
+    ("--values", "scan.values"),
+    ("--estimator", "scan.estimator"),
+    ("--dilution", "dilution.kind"),
+    ("--realizations", "dilution.realizations"),
+    ("--spin", "model.spin"),
+    ("--algorithm", "model.algorithm"),
+    ("--t-min", "temperature.min"),
+    ("--t-max", "temperature.max"),
+    ("--betas", "temperature.betas"),
+    ("--spacing", "temperature.spacing"),
+    ("--trials", "run.trials"),
+    ("--seed", "run.seed"),
+    ("--from-snapshot", "run.from_snapshot"),
+    ("--threads", "run.threads"),
+    ("--layers", "search.layers"),
+    ("--per-layer", "search.per_layer"),
+    ("--peak", "refine.peak"),
+    ("--tolerance", "refine.tolerance"),
+    ("--max-betas", "refine.max_betas"),
+    ("--sizes", "scaling.sizes"),
+    ("--temperature", "render.temperature"),
+    ("--output", "output.dir"),
+    ("--name", "output.name"),
+    ("--resume", "output.resume"),
+    ("--series", "output.series"),
+    ("--snapshots", "output.snapshots"),
+    ("--log-level", "log.level"),
+    ("--log-file", "log.file"),
+    ("--config", "config"),
+];
+
+impl Options {
+    /// Size given on the command line, or the usual one for the lattice family
+    pub fn size(&self) -> usize {
+        self.size.unwrap_or(match self.lattice.as_str() {
+            "cubic" | "bcc" => 16,
+            "penrose" => 9,
+            "penrose-cp" => 40,
+            _ => 128,
+        })
+    }
+
+    /// Options of one entry of `--sizes`, which is a patch of einstein lattices and a size of others
+    pub fn at_size(&self, entry: &str) -> Result<Options, String> {
+        let mut options = self.clone();
+        options.apply(if self.lattice == "einstein" { "lattice.patch" } else { "lattice.size" }, entry)?;
+        Ok(options)
+    }
+
+    /// Options with the phase diagram parameter set to `value`. The parameter is a config key such
+    /// as `couplings.eta`, or the name of its flag such as `eta`, so any numeric option can be
+    /// scanned.
+    pub fn at_parameter(&self, value: f32) -> Result<Options, String> {
+        let key = if self.parameter.contains('.') {
+            self.parameter.as_str()
+        } else {
+            let flag = format!("--{}", self.parameter);
+            KEYS.iter().find(|(f, _)| *f == flag).map(|(_, key)| *key)
+                .ok_or_else(|| format!("no option is named {:?}", self.parameter))?
+        };
+        let mut options = self.clone();
+        options.apply(key, &value.to_string())?;
+        Ok(options)
+    }
+
+    /// Values of the phase diagram parameter: `values`, or `steps` of them from `from` to `to`
+    pub fn parameter_values(&self) -> Vec<f32> {
+        if self.values.is_empty() {
+            linspace(self.from, self.to, self.steps)
+        } else {
+            self.values.clone()
+        }
+    }
+
+    /// Occupations of a dilution or percolation run: `values`, or `steps` of them from 0.5 to 1
+    pub fn occupations(&self) -> Vec<f32> {
+        if self.values.is_empty() {
+            linspace(0.5, 1., self.steps)
+        } else {
+            self.values.clone()
+        }
+    }
+
+    /// Monte Carlo algorithm, which is fixed by the spin model
+    pub fn algorithm(&self) -> &str {
+        self.algorithm.as_deref().unwrap_or(if self.spin == "tim" { "continuous-wolff" } else { "wolff" })
+    }
+
+    /// Couplings `(t1, t2)` of rect and einstein lattices at `eta`, unless given explicitly
+    pub fn couplings(&self, eta: f32) -> (f32, f32) {
+        (self.t1.unwrap_or_else(|| get_t1_from_eta(eta)), self.t2.unwrap_or_else(|| get_t2_from_eta(eta)))
+    }
+
+    /// Betas of a sweep, from hot to cold
+    pub fn betas(&self) -> Vec<f32> {
+        if self.spacing == "beta" {
+            linspace(1. / self.t_max, 1. / self.t_min, self.num_betas)
+        } else {
+            reciprocal_linspace(self.t_min, self.t_max, self.num_betas)
+        }
+    }
+
+    /// Sets the option of config file `key`, where booleans are `true` or `false`
+    pub fn apply(&mut self, key: &str, word: &str) -> Result<(), String> {
+        fn value<T: std::str::FromStr>(key: &str, word: &str) -> Result<T, String> {
+            word.parse().map_err(|_| format!("could not parse {:?} for {}", word, key))
+        }
+        match key {
+            "lattice.family" => self.lattice = word.to_owned(),
+            "lattice.size" => self.size = Some(value(key, word)?),
+            "lattice.patch" => self.patch = word.to_owned(),
+            "couplings.eta" => self.eta = value(key, word)?,
+            "couplings.t1" => self.t1 = Some(value(key, word)?),
+            "couplings.t2" => self.t2 = Some(value(key, word)?),
+            "scan.parameter" => self.parameter = word.to_owned(),
+            "scan.from" => self.from = value(key, word)?,
+            "scan.to" => self.to = value(key, word)?,
+            "scan.steps" => self.steps = value(key, word)?,
+            "scan.values" => self.values = word.split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+                .map(|s| value(key, s)).collect::<Result<_, _>>()?,
+            "scan.estimator" => self.estimator = word.to_owned(),
+            "dilution.kind" => self.dilution = word.to_owned(),
+            "dilution.realizations" => self.realizations = value(key, word)?,
+            "model.spin" => self.spin = word.to_owned(),
+            "model.algorithm" => self.algorithm = Some(word.to_owned()),
+            "temperature.min" => self.t_min = value(key, word)?,
+            "temperature.max" => self.t_max = value(key, word)?,
+            "temperature.betas" => self.num_betas = value(key, word)?,
+            "temperature.spacing" => self.spacing = word.to_owned(),
+            "run.trials" => self.trials = value(key, word)?,
+            "run.seed" => self.seed = Some(value(key, word)?),
+            "run.from_snapshot" => self.from_snapshot = Some(word.to_owned()),
+            "run.threads" => self.threads = value(key, word)?,
+            "search.layers" => self.layers = value(key, word)?,
+            "search.per_layer" => self.per_layer = value(key, word)?,
+            "refine.peak" => self.peak = word.to_owned(),
+            "refine.tolerance" => self.tolerance = value(key, word)?,
+            "refine.max_betas" => self.max_betas = value(key, word)?,
+            "scaling.sizes" => self.sizes = word.split(',').map(|s| s.trim().to_owned()).filter(|s| !s.is_empty()).collect(),
+            "render.temperature" => self.temperature = value(key, word)?,
+            "output.dir" => self.output_dir = word.to_owned(),
+            "output.name" => self.name = Some(word.to_owned()),
+            "output.resume" => self.resume = value(key, word)?,
+            "output.series" => self.series = value(key, word)?,
+            "output.snapshots" => self.snapshots = value(key, word)?,
+            "log.level" => self.log_level = word.to_owned(),
+            "log.file" => self.log_file = Some(word.to_owned()),
+            _ => return Err(format!("unknown config key {:?}", key)),
+        }
+        Ok(())
+    }
+
+    /// Every option, resolved, as a config file that runs `command` again
+    pub fn to_toml(&self, command: Command) -> String {
+        let q = |s: &str| config::quote(s);
+        let mut out = format!("# quasing-model {}\ncommand = {}\n", code_version(), q(command.name()));
+        out += &format!("\n[lattice]\nfamily = {}\nsize = {}\npatch = {}\n", q(&self.lattice), self.size(), q(&self.patch));
+        // Couplings mapped from eta are left out, so that a config scanned over eta still follows it
+        out += &format!("\n[couplings]\neta = {:?}\n", self.eta);
+        if let Some(t1) = self.t1 {
+            out += &format!("t1 = {:?}\n", t1);
+        }
+        if let Some(t2) = self.t2 {
+            out += &format!("t2 = {:?}\n", t2);
+        }
+        let values = self.values.iter().map(|v| format!("{:?}", v)).collect::<Vec<_>>().join(",");
+        out += &format!("\n[scan]\nparameter = {}\nfrom = {:?}\nto = {:?}\nsteps = {}\nvalues = {}\nestimator = {}\n",
+            q(&self.parameter), self.from, self.to, self.steps, q(&values), q(&self.estimator));
+        out += &format!("\n[dilution]\nkind = {}\nrealizations = {}\n", q(&self.dilution), self.realizations);
+        out += &format!("\n[model]\nspin = {}\nalgorithm = {}\n", q(&self.spin), q(self.algorithm()));
+        out += &format!("\n[temperature]\nmin = {:?}\nmax = {:?}\nbetas = {}\nspacing = {}\n",
+            self.t_min, self.t_max, self.num_betas, q(&self.spacing));
+        out += &format!("\n[run]\ntrials = {}\n", self.trials);
+        if let Some(seed) = self.seed {
+            out += &format!("seed = {}\n", seed);
+        }
+        if let Some(snapshot) = &self.from_snapshot {
+            out += &format!("from_snapshot = {}\n", q(snapshot));
+        }
+        out += &format!("threads = {}\n", self.threads);
+        out += &format!("\n[search]\nlayers = {}\nper_layer = {}\n", self.layers, self.per_layer);
+        out += &format!("\n[refine]\npeak = {}\ntolerance = {:?}\nmax_betas = {}\n", q(&self.peak), self.tolerance, self.max_betas);
+        out += &format!("\n[scaling]\nsizes = {}\n", q(&self.sizes.join(",")));
+        out += &format!("\n[render]\ntemperature = {:?}\n", self.temperature);
+        out += &format!("\n[output]\ndir = {}\n", q(&self.output_dir));
+        if let Some(name) = &self.name {
+            out += &format!("name = {}\n", q(name));
+        }
+        out += &format!("resume = {}\nseries = {}\nsnapshots = {}\n", self.resume, self.series, self.snapshots);
+        out += &format!("\n[log]\nlevel = {}\n", q(&self.log_level));
+        if let Some(file) = &self.log_file {
+            out += &format!("file = {}\n", q(file));
+        }
+        out
+    }
+
+    /// Writes the resolved options to `{output_dir}/{name}.toml`, next to the output `name`
+    pub fn save(&self, command: Command, name: &str) -> Result<(), LoadError> {
+        let filename = format!("{}/{}.toml", self.output_dir, name);
+        std::fs::create_dir_all(&self.output_dir)
+            .and_then(|_| std::fs::write(&filename, self.to_toml(command)))
+            .map_err(|source| LoadError::Io { file: filename, source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `options` written as the config of `command` and read back
+    fn round_trip(options: &Options, name: &str) -> Options {
+        let output_dir = std::env::temp_dir().join(format!("quasing-options-{}", std::process::id()));
+        let options = Options { output_dir: output_dir.to_str().unwrap().to_owned(), ..options.clone() };
+        options.save(Command::Phase, name).unwrap();
+        let filename = format!("{}/{}.toml", options.output_dir, name);
+        let mut loaded = Options::default();
+        for (key, value) in config::load(&filename).unwrap() {
+            if key != "command" {
+                loaded.apply(&key, &value).unwrap();
+            }
+        }
+        std::fs::remove_file(&filename).unwrap();
+        loaded
+    }
+
+    #[test]
+    fn saved_couplings_follow_eta() {
+        let options = Options { eta: 0.5, seed: Some(3), ..Options::default() };
+        let loaded = round_trip(&options, "follow-eta");
+        assert_eq!((loaded.t1, loaded.t2), (None, None));
+        for eta in [-0.5, 0., 0.5] {
+            let scanned = loaded.at_parameter(eta).unwrap();
+            assert_eq!(scanned.couplings(scanned.eta), (get_t1_from_eta(eta), get_t2_from_eta(eta)));
+        }
+    }
+
+    #[test]
+    fn sizes_are_checked() {
+        let options = Options { lattice: "bcc".to_owned(), ..Options::default() };
+        assert_eq!(options.at_size("12").unwrap().size(), 12);
+        assert!(options.at_size("twelve").is_err());
+        let einstein = Options { lattice: "einstein".to_owned(), ..Options::default() };
+        assert_eq!(einstein.at_size("20k").unwrap().patch, "20k");
+    }
+
+    #[test]
+    fn explicit_couplings_are_kept() {
+        let options = Options { t1: Some(0.25), ..Options::default() };
+        let loaded = round_trip(&options, "explicit-t1");
+        assert_eq!(loaded.t1, Some(0.25));
+        assert_eq!(loaded.couplings(0.5), (0.25, get_t2_from_eta(0.5)));
+    }
+}
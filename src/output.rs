@@ -0,0 +1,218 @@
+use std::{fs::File, io::{BufRead, BufReader}, path::Path};
+
+use crate::lattice::Report;
+use crate::validate::LoadError;
+
+/// Columns of the output table, in order
+pub const COLUMNS: [&str; 13] = [
+    "beta", "temperature",
+    "magnetization", "magnetization_error",
+    "susceptibility", "susceptibility_error",
+    "energy", "energy_error",
+    "specific_heat", "specific_heat_error",
+    "binder", "binder_error",
+    "num_measurements",
+];
+
+/// Git commit the crate was built from, if it was built in a checkout
+pub fn git_commit() -> Option<&'static str> {
+    Some(env!("QUASING_GIT_COMMIT")).filter(|commit| !commit.is_empty())
+}
+
+/// Whether the checkout had uncommitted changes when the crate was built
+pub fn git_dirty() -> Option<bool> {
+    match env!("QUASING_GIT_DIRTY") {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Version of the crate with the short commit it was built from and `-dirty` for uncommitted
+/// changes, e.g. `0.1.0+b0b3d4d-dirty`
+pub fn code_version() -> String {
+    match git_commit() {
+        Some(commit) => format!("{}+{}{}", env!("CARGO_PKG_VERSION"), &commit[..7.min(commit.len())],
+            if git_dirty() == Some(true) { "-dirty" } else { "" }),
+        None => env!("CARGO_PKG_VERSION").to_owned(),
+    }
+}
+
+/// Writes `rows` under `header` to `{output_dir}/{filename}.csv`, and `run` to
+/// `{output_dir}/{filename}.json`, creating `output_dir` if needed
+pub fn write_table(output_dir: &str, filename: &str, header: &str, rows: impl IntoIterator<Item = String>, run: &Run) -> Result<(), LoadError> {
+    let io_error = |file: &Path, source| LoadError::Io { file: file.display().to_string(), source };
+    let path = Path::new(output_dir);
+    std::fs::create_dir_all(path).map_err(|e| io_error(path, e))?;
+    let mut csv = format!("{}\n", header);
+    for row in rows {
+        csv += &row;
+        csv.push('\n');
+    }
+    let (csv_file, json_file) = (path.join(format!("{}.csv", filename)), path.join(format!("{}.json", filename)));
+    std::fs::write(&csv_file, csv).map_err(|e| io_error(&csv_file, e))?;
+    std::fs::write(&json_file, run.to_json()).map_err(|e| io_error(&json_file, e))
+}
+
+/// Parameters of a run, written next to its data
+#[derive(Clone)]
+pub struct Run {
+    pub lattice: String,
+    pub spin: String,
+    /// Named lattice parameters such as eta, t1 and t2
+    pub parameters: Vec<(String, f32)>,
+    pub n_trials: usize,
+    pub seed: Option<u64>,
+    /// Columns of the table the run wrote
+    pub columns: Vec<String>,
+}
+
+/// Observables of a temperature sweep
+#[derive(Default)]
+pub struct Data {
+    pub betas: Vec<f32>,
+    pub reports: Vec<Report>,
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// JSON number, with `null` in place of NaN and infinities
+fn json_number(x: f32) -> String {
+    if x.is_finite() { x.to_string() } else { "null".to_owned() }
+}
+
+impl Run {
+    pub fn new(lattice: &str, spin: &str, n_trials: usize) -> Self {
+        Self {
+            lattice: lattice.to_owned(),
+            spin: spin.to_owned(),
+            parameters: Vec::new(),
+            n_trials,
+            seed: None,
+            columns: COLUMNS.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    /// Sets parameter `name`, replacing any value it already has
+    pub fn with_parameter(mut self, name: &str, value: f32) -> Self {
+        match self.parameters.iter_mut().find(|(n, _)| n == name) {
+            Some(parameter) => parameter.1 = value,
+            None => self.parameters.push((name.to_owned(), value)),
+        }
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_columns(mut self, columns: &[&str]) -> Self {
+        self.columns = columns.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Metadata as a JSON object
+    pub fn to_json(&self) -> String {
+        let parameters = self.parameters.iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), json_number(*value)))
+            .collect::<Vec<_>>().join(", ");
+        let columns = self.columns.iter().map(|c| json_string(c)).collect::<Vec<_>>().join(", ");
+        let mut out = String::from("{\n");
+        out += &format!("  \"lattice\": {},\n", json_string(&self.lattice));
+        out += &format!("  \"spin\": {},\n", json_string(&self.spin));
+        out += &format!("  \"parameters\": {{{}}},\n", parameters);
+        out += &format!("  \"n_trials\": {},\n", self.n_trials);
+        out += &format!("  \"seed\": {},\n", self.seed.map_or("null".to_owned(), |s| s.to_string()));
+        out += &format!("  \"code_version\": {},\n", json_string(&code_version()));
+        out += &format!("  \"git_commit\": {},\n", git_commit().map_or("null".to_owned(), json_string));
+        out += &format!("  \"git_dirty\": {},\n", git_dirty().map_or("null".to_owned(), |d| d.to_string()));
+        out += &format!("  \"columns\": [{}]\n", columns);
+        out += "}\n";
+        out
+    }
+}
+
+/// One line of the output table, without a newline
+pub fn row(beta: f32, r: &Report) -> String {
+    format!("{},{},{},{},{},{},{},{},{},{},{},{},{}", beta, 1. / beta,
+        r.magnetization, r.magnetization_error,
+        r.susceptibility, r.susceptibility_error,
+        r.energy, r.energy_error,
+        r.specific_heat, r.specific_heat_error,
+        r.binder, r.binder_error,
+        r.num_measurements)
+}
+
+/// Reads a line written by `row`
+pub fn parse_row(line: &str) -> Option<(f32, Report)> {
+    let words = line.trim().split(',').collect::<Vec<_>>();
+    if words.len() != COLUMNS.len() {
+        return None;
+    }
+    let x = words[..12].iter().map(|w| w.parse::<f32>()).collect::<Result<Vec<_>, _>>().ok()?;
+    Some((x[0], Report {
+        magnetization: x[2],
+        magnetization_error: x[3],
+        susceptibility: x[4],
+        susceptibility_error: x[5],
+        energy: x[6],
+        energy_error: x[7],
+        specific_heat: x[8],
+        specific_heat_error: x[9],
+        binder: x[10],
+        binder_error: x[11],
+        num_measurements: words[12].parse().ok()?,
+    }))
+}
+
+impl Data {
+    pub fn push(&mut self, beta: f32, report: Report) {
+        self.betas.push(beta);
+        self.reports.push(report);
+    }
+
+    /// The same rows in order of increasing beta
+    pub fn sorted(self) -> Self {
+        let mut rows = self.betas.into_iter().zip(self.reports).collect::<Vec<_>>();
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (betas, reports) = rows.into_iter().unzip();
+        Self { betas, reports }
+    }
+
+    /// Writes `{output_dir}/{filename}.csv`, with a header naming every column, and the run
+    /// parameters to `{output_dir}/{filename}.json`
+    pub fn save(&self, output_dir: &str, filename: &str, run: &Run) -> Result<(), LoadError> {
+        let rows = self.betas.iter().zip(&self.reports).map(|(beta, report)| row(*beta, report));
+        write_table(output_dir, filename, &COLUMNS.join(","), rows, run)
+    }
+
+    /// Reads `{output_dir}/{filename}.csv`, written by `save`
+    pub fn load(output_dir: &str, filename: &str) -> Result<Self, LoadError> {
+        let file = Path::new(output_dir).join(format!("{}.csv", filename)).to_string_lossy().into_owned();
+        let f = File::open(&file).map_err(|source| LoadError::Io { file: file.clone(), source })?;
+        let mut data = Data::default();
+        for (i, line) in BufReader::new(f).lines().enumerate().skip(1) {
+            let line = line.map_err(|source| LoadError::Io { file: file.clone(), source })?;
+            let (beta, report) = parse_row(&line).ok_or_else(|| {
+                LoadError::Parse { file: file.clone(), line: Some(i + 1), message: "could not parse output row".to_owned() }
+            })?;
+            data.push(beta, report);
+        }
+        Ok(data)
+    }
+}
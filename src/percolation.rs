@@ -0,0 +1,161 @@
+use std::path::{Path, PathBuf};
+
+use crate::lattice::Lattice;
+use crate::sampler::Sampler;
+use crate::spin::Ising;
+use crate::validate::LoadError;
+
+/// Percolation observables of a diluted lattice, averaged over dilution samples
+pub struct Percolation {
+    pub occupation: f32,
+    /// Fraction of samples with a cluster touching both sides
+    pub spanning: f32,
+    /// Size of the largest cluster as a fraction of the undiluted lattice
+    pub largest_fraction: f32,
+    /// Entry `s` is the number of clusters of size `s` per site of the undiluted lattice
+    pub cluster_sizes: Vec<f32>,
+}
+
+/// Measures percolation on `samples` dilutions of a lattice with `num_sites` sites. `dilute` makes
+/// the dilution for a seed and returns the original index of every kept site together with its
+/// neighbor function, as `site_dilute_fn` does. `sides` are two sets of original site indices, and
+/// a cluster spans when it contains a site from each.
+pub fn percolation<F: Fn(usize) -> Vec<(usize, f32)>>(num_sites: usize, occupation: f32, samples: usize,
+    dilute: impl Fn(u64) -> (Vec<usize>, F), sides: &[Vec<usize>; 2]) -> Percolation {
+
+    let mut on_side = vec![[false; 2]; num_sites];
+    for (side, sites) in sides.iter().enumerate() {
+        for site in sites {
+            on_side[*site][side] = true;
+        }
+    }
+
+    let mut spanning = 0.;
+    let mut largest_fraction = 0.;
+    let mut cluster_sizes = vec![0.; num_sites + 1];
+    for seed in 0..samples {
+        let (kept, func) = dilute(seed as u64);
+        if kept.is_empty() { continue; }
+        let mut lattice = Lattice::<Ising>::new(kept.len(), &func);
+        let mut spans = false;
+        let mut largest = 0;
+        for cluster in lattice.clusters() {
+            largest = largest.max(cluster.len());
+            cluster_sizes[cluster.len()] += 1.;
+            let touches = |side: usize| { cluster.iter().any(|site| on_side[kept[*site]][side]) };
+            spans = spans || (touches(0) && touches(1));
+        }
+        spanning += spans as i32 as f32;
+        largest_fraction += largest as f32 / num_sites as f32;
+    }
+
+    while cluster_sizes.len() > 1 && cluster_sizes[cluster_sizes.len() - 1] == 0. {
+        cluster_sizes.pop();
+    }
+    for n in cluster_sizes.iter_mut() {
+        *n /= (samples * num_sites) as f32;
+    }
+    Percolation {
+        occupation,
+        spanning: spanning / samples as f32,
+        largest_fraction: largest_fraction / samples as f32,
+        cluster_sizes,
+    }
+}
+
+/// Estimates the percolation threshold as the occupation where the spanning probability crosses
+/// one half, interpolating linearly between grid points. Assumes `results` is sorted by occupation.
+pub fn threshold(results: &[Percolation]) -> Option<f32> {
+    for pair in results.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if a.spanning < 0.5 && b.spanning >= 0.5 {
+            return Some(a.occupation + (0.5 - a.spanning) / (b.spanning - a.spanning) * (b.occupation - a.occupation));
+        }
+    }
+    None
+}
+
+/// Writes `{output_dir}/{filename}.csv` with one row per occupation, and the cluster size
+/// distributions to `{output_dir}/{filename}-sizes.csv` with one row per occupation and size
+pub fn save(results: &[Percolation], output_dir: &str, filename: &str) -> Result<(), LoadError> {
+    let mut summary = String::from("occupation,spanning,largest_fraction\n");
+    let mut sizes = String::from("occupation,size,density\n");
+    for entry in results {
+        summary += &format!("{},{},{}\n", entry.occupation, entry.spanning, entry.largest_fraction);
+        for (size, density) in entry.cluster_sizes.iter().enumerate() {
+            if *density > 0. {
+                sizes += &format!("{},{},{}\n", entry.occupation, size, density);
+            }
+        }
+    }
+
+    let path = Path::new(output_dir);
+    let write = |file: PathBuf, contents: &str| std::fs::create_dir_all(path)
+        .and_then(|_| std::fs::write(&file, contents))
+        .map_err(|source| LoadError::Io { file: file.display().to_string(), source });
+    write(path.join(format!("{}.csv", filename)), &summary)?;
+    write(path.join(format!("{}-sizes.csv", filename)), &sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::funcs::{bond_dilute_fn, fibonacci_grid_fn, site_dilute_fn};
+
+    /// Open square grid of `size^2` sites
+    fn grid(size: usize) -> impl Fn(usize) -> Vec<(usize, f32)> {
+        fibonacci_grid_fn(size, 1., 1., false).unwrap()
+    }
+
+    /// Left and right columns of `grid(size)`
+    fn sides(size: usize) -> [Vec<usize>; 2] {
+        [(0..size).map(|y| y * size).collect(), (0..size).map(|y| y * size + size - 1).collect()]
+    }
+
+    fn site_percolation(size: usize, occupation: f32, samples: usize) -> Percolation {
+        let func = grid(size);
+        percolation(size * size, occupation, samples, |seed| site_dilute_fn(size * size, &func, occupation, seed), &sides(size))
+    }
+
+    #[test]
+    fn full_lattice_is_one_spanning_cluster() {
+        let result = site_percolation(8, 1., 10);
+        assert_eq!(result.spanning, 1.);
+        assert_eq!(result.largest_fraction, 1.);
+        assert_eq!(result.cluster_sizes.len(), 65);
+        assert_eq!(result.cluster_sizes[64], 1. / 64.);
+        assert!(result.cluster_sizes[..64].iter().all(|n| *n == 0.));
+    }
+
+    #[test]
+    fn empty_lattice_has_no_clusters() {
+        let result = site_percolation(8, 0., 10);
+        assert_eq!((result.spanning, result.largest_fraction), (0., 0.));
+    }
+
+    #[test]
+    fn without_bonds_every_site_is_its_own_cluster() {
+        let func = grid(8);
+        let result = percolation(64, 0., 5, |seed| ((0..64).collect(), bond_dilute_fn(64, &func, 0., seed)), &sides(8));
+        assert_eq!(result.spanning, 0.);
+        assert_eq!(result.largest_fraction, 1. / 64.);
+        assert_eq!(result.cluster_sizes, vec![0., 1.]);
+    }
+
+    #[test]
+    fn square_grid_percolates_between_low_and_high_occupation() {
+        assert!(site_percolation(16, 0.3, 50).spanning < 0.1);
+        assert!(site_percolation(16, 0.9, 50).spanning > 0.9);
+        let results = [0.3, 0.5, 0.7, 0.9].map(|p| site_percolation(16, p, 50));
+        let threshold = threshold(&results).unwrap();
+        assert!((0.5..0.7).contains(&threshold), "threshold {}", threshold);
+    }
+
+    #[test]
+    fn threshold_interpolates_where_half_span() {
+        let result = |occupation, spanning| Percolation { occupation, spanning, largest_fraction: 0., cluster_sizes: Vec::new() };
+        let results = [result(0.2, 0.), result(0.4, 0.25), result(0.6, 0.75), result(0.8, 1.)];
+        assert!((threshold(&results).unwrap() - 0.5).abs() < 1e-6);
+        assert_eq!(threshold(&results[..2]), None);
+    }
+}
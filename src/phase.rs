@@ -0,0 +1,91 @@
+//! Phase diagrams: Tc against a lattice parameter
+
+use crate::analysis::{self, Estimate, Estimator, ESTIMATORS};
+use crate::drivers::{build_geometry, finished, sweep, RunError};
+use crate::geometry::Geometry;
+use crate::jobs;
+use crate::log;
+use crate::options::{Command, Options};
+use crate::output::{write_table, Data, Run};
+use crate::sampler::Sampler;
+
+/// Tc at one point of the diagram
+pub struct Point {
+    /// Value of the parameter
+    pub value: f32,
+    /// Estimate of each of `ESTIMATORS`, if the sweep supports it
+    pub estimates: Vec<Option<Estimate>>,
+}
+
+/// Column names of a phase diagram over `parameter`. `tc` is by the chosen estimator, and every
+/// estimator follows.
+pub fn columns(parameter: &str) -> Vec<String> {
+    let mut columns = [parameter, "tc", "tc_error"].map(|c| c.to_owned()).to_vec();
+    for estimator in ESTIMATORS {
+        let name = estimator.name().replace('-', "_");
+        columns.push(format!("tc_{}", name));
+        columns.push(format!("tc_{}_error", name));
+    }
+    columns
+}
+
+/// Writes the phase diagram as `{output_dir}/{filename}.csv`, with missing estimates as NaN, and
+/// `run` to `{output_dir}/{filename}.json`
+pub fn save(points: &[Point], parameter: &str, estimator: &str, output_dir: &str, filename: &str, run: Run) -> Result<(), RunError> {
+    let columns = columns(parameter);
+    let chosen = ESTIMATORS.iter().position(|e| Some(*e) == Estimator::from_name(estimator))
+        .ok_or_else(|| RunError::Options(format!("unknown estimator {:?}", estimator)))?;
+    let rows = points.iter().map(|point| {
+        let estimate = |e: &Option<Estimate>| e.map_or(format!("{},{}", f32::NAN, f32::NAN), |e| format!("{},{}", e.temperature, e.error));
+        let all = point.estimates.iter().map(estimate).collect::<Vec<_>>().join(",");
+        format!("{},{},{}", point.value, estimate(&point.estimates[chosen]), all)
+    });
+    let run = run.with_columns(&columns.iter().map(|c| c.as_str()).collect::<Vec<_>>());
+    write_table(output_dir, filename, &columns.join(","), rows, &run)?;
+    Ok(())
+}
+
+/// Sweeps the lattice `family(value)` at each of `values` of `parameter`, each one a task for the
+/// `--threads` workers that is saved as a `sweep`, and tabulates Tc against the parameter in a
+/// column named after it. `parameter` names an option, as for `Options::at_parameter`, which is
+/// set in the config of each sweep so that it reruns that point. A value the option does not take
+/// stops the diagram.
+pub fn phase_diagram<L: Sampler>(options: &Options, parameter: &str, values: &[f32],
+    family: impl Fn(f32) -> Result<(Geometry, Run), RunError> + Sync) -> Result<(), RunError> {
+
+    log::info(&format!("{} {} phase diagram over {}", L::model(), options.lattice, parameter));
+    let scanned = Options { parameter: parameter.to_owned(), ..options.clone() };
+    let points = jobs::run(values.to_vec(), options.threads, |index, value| -> Result<_, RunError> {
+        let name = format!("{}-{}-{}{:.8}", options.lattice, L::model(), parameter, value);
+        let task = Options {
+            seed: options.seed.map(|seed| seed.wrapping_add(index as u64)),
+            name: Some(name.clone()),
+            ..scanned.at_parameter(value).map_err(RunError::Options)?
+        };
+        let data = if finished(&task, &name) {
+            Data::load(&task.output_dir, &name)?
+        } else {
+            let (geometry, run) = family(value)?;
+            let mut lattice = L::from_geometry(&geometry);
+            sweep(&mut lattice, &task, Command::Sweep, &name, run.with_parameter(parameter, value))?
+        };
+        let estimates = ESTIMATORS.iter().map(|estimator| analysis::estimate(&data, *estimator)).collect();
+        Ok(Point { value, estimates })
+    }).into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let name = options.name.clone().unwrap_or_else(|| format!("{}-{}-phase", options.lattice, L::model()));
+    options.save(Command::Phase, &name)?;
+    let mut run = Run::new(&options.lattice, &L::model(), options.trials);
+    if let Some(seed) = options.seed {
+        run = run.with_seed(seed);
+    }
+    save(&points, parameter, &options.estimator, &options.output_dir, &name, run)
+}
+
+/// Phase diagram of the lattice of `options` over `--parameter`, which may be any numeric option
+pub fn scan<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    phase_diagram::<L>(options, &options.parameter, &options.parameter_values(), |value| {
+        let task = options.at_parameter(value).map_err(RunError::Options)?;
+        build_geometry(&task, task.eta)
+    })
+}
@@ -0,0 +1,401 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::geometry::{Geometry, Boundary};
+
+/// Acceptance domain in perpendicular space
+pub enum Window {
+    /// Projection of the unit hypercube of the hyperlattice, centered on the origin
+    Hypercube,
+    /// Sphere of the given radius
+    Ball(f32),
+    /// Intersection of slabs `|normal . x| < half_width`
+    Polytope(Vec<(Vec<f32>, f32)>),
+}
+
+/// How the sites of a tiling are bonded together
+pub enum BondRule {
+    /// Bond sites whose hyperlattice points differ by one unit vector
+    Hyperlattice,
+    /// Bond sites whose physical distance lies in `(min, max]`
+    Distance(f32, f32),
+}
+
+/// A site of a projected tiling
+pub struct Site {
+    pub hyper: Vec<i32>,
+    pub physical: Vec<f32>,
+    pub perpendicular: Vec<f32>,
+}
+
+/// Sites and bonds of a finite patch of a projected tiling. Each bond is listed once.
+pub struct Tiling {
+    pub sites: Vec<Site>,
+    pub bonds: Vec<(usize, usize)>,
+}
+
+/// Cut-and-project construction from the hypercubic lattice Z^n. Axis `i` of the hyperlattice is
+/// mapped to `physical[i]` in physical space and `perpendicular[i]` in perpendicular space.
+pub struct CutProject {
+    physical: Vec<Vec<f32>>,
+    perpendicular: Vec<Vec<f32>>,
+    window: Window,
+    offset: Vec<f32>,
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn determinant(mut matrix: Vec<Vec<f32>>) -> f32 {
+    let n = matrix.len();
+    let mut det = 1.;
+    for col in 0..n {
+        let pivot = (col..n).max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs())).unwrap();
+        if matrix[pivot][col] == 0. {
+            return 0.;
+        }
+        if pivot != col {
+            matrix.swap(pivot, col);
+            det = -det;
+        }
+        det *= matrix[col][col];
+        let (top, bottom) = matrix.split_at_mut(col + 1);
+        for row in bottom {
+            let factor = row[col] / top[col][col];
+            for (x, y) in row[col..].iter_mut().zip(&top[col][col..]) {
+                *x -= factor * y;
+            }
+        }
+    }
+    det
+}
+
+/// Vector orthogonal to the `d-1` given vectors in `d` dimensions (the generalized cross product)
+fn normal(vectors: &[&Vec<f32>], dim: usize) -> Vec<f32> {
+    (0..dim).map(|i| {
+        let minor = vectors.iter().map(|v| {
+            v.iter().enumerate().filter(|(k, _)| *k != i).map(|(_, x)| *x).collect()
+        }).collect();
+        if i % 2 == 0 { determinant(minor) } else { -determinant(minor) }
+    }).collect()
+}
+
+fn subsets(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    let mut out = Vec::new();
+    for last in (k-1)..n {
+        for mut subset in subsets(last, k - 1) {
+            subset.push(last);
+            out.push(subset);
+        }
+    }
+    out
+}
+
+impl CutProject {
+    /// Create a projection from the images of each hyperlattice axis
+    pub fn new(physical: Vec<Vec<f32>>, perpendicular: Vec<Vec<f32>>, window: Window) -> Self {
+        assert_eq!(physical.len(), perpendicular.len(), "Every hyperlattice axis needs a physical and perpendicular image");
+        let offset = vec![0.; perpendicular[0].len()];
+        Self { physical, perpendicular, window, offset }
+    }
+
+    /// Shift the window in perpendicular space. A generic shift avoids singular tilings.
+    pub fn with_offset(mut self, offset: Vec<f32>) -> Self {
+        assert_eq!(offset.len(), self.offset.len());
+        self.offset = offset;
+        self
+    }
+
+    /// Rhombic Penrose tiling from Z^5. The third perpendicular axis separates the five layers of the
+    /// hyperlattice, and its offset of one half selects the true Penrose tiling.
+    pub fn penrose() -> Self {
+        let angle = |k: usize, m: usize| { 2. * std::f32::consts::PI * (k * m) as f32 / 5. };
+        Self::new(
+            (0..5).map(|k| vec![angle(k, 1).cos(), angle(k, 1).sin()]).collect(),
+            (0..5).map(|k| vec![angle(k, 2).cos(), angle(k, 2).sin(), 1.]).collect(),
+            Window::Hypercube,
+        ).with_offset(vec![0.01234, 0.00567, 0.5])
+    }
+
+    /// Ammann-Beenker square and rhombus tiling from Z^4
+    pub fn ammann_beenker() -> Self {
+        let angle = |k: usize, m: usize| { std::f32::consts::PI * (k * m) as f32 / 4. };
+        Self::new(
+            (0..4).map(|k| vec![angle(k, 1).cos(), angle(k, 1).sin()]).collect(),
+            (0..4).map(|k| vec![angle(k, 3).cos(), angle(k, 3).sin()]).collect(),
+            Window::Hypercube,
+        ).with_offset(vec![0.01234, 0.00567])
+    }
+
+    /// Dodecagonal square, triangle-pair and rhombus tiling from Z^6
+    pub fn dodecagonal() -> Self {
+        let angle = |k: usize, m: usize| { std::f32::consts::PI * (k * m) as f32 / 6. };
+        Self::new(
+            (0..6).map(|k| vec![angle(k, 1).cos(), angle(k, 1).sin()]).collect(),
+            (0..6).map(|k| vec![angle(k, 5).cos(), angle(k, 5).sin(), angle(k, 3).cos(), angle(k, 3).sin()]).collect(),
+            Window::Hypercube,
+        ).with_offset(vec![0.01234, 0.00567, 0.00311, 0.00789])
+    }
+
+    /// Fibonacci chain from Z^2, with long and short bonds of ratio tau
+    pub fn fibonacci_chain() -> Self {
+        let tau = (1. + 5f32.sqrt()) / 2.;
+        let (cos, sin) = (tau / (1. + tau * tau).sqrt(), 1. / (1. + tau * tau).sqrt());
+        Self::new(
+            vec![vec![cos], vec![sin]],
+            vec![vec![-sin], vec![cos]],
+            Window::Hypercube,
+        ).with_offset(vec![0.01234])
+    }
+
+    /// Product of two Fibonacci chains from Z^4
+    pub fn fibonacci_grid() -> Self {
+        let tau = (1. + 5f32.sqrt()) / 2.;
+        let (cos, sin) = (tau / (1. + tau * tau).sqrt(), 1. / (1. + tau * tau).sqrt());
+        Self::new(
+            vec![vec![cos, 0.], vec![sin, 0.], vec![0., cos], vec![0., sin]],
+            vec![vec![-sin, 0.], vec![cos, 0.], vec![0., -sin], vec![0., cos]],
+            Window::Hypercube,
+        ).with_offset(vec![0.01234, 0.00567])
+    }
+
+    /// Ammann rhombohedral (icosahedral) tiling from Z^6. The six axes map to the five-fold axes of
+    /// the icosahedron in physical space and to their Galois conjugates (tau -> -1/tau) in
+    /// perpendicular space, and edges have unit length.
+    pub fn icosahedral() -> Self {
+        let tau = (1. + 5f32.sqrt()) / 2.;
+        let norm = 1. / (1. + tau * tau).sqrt();
+        let scale = |v: [f32; 3]| { v.iter().map(|x| x * norm).collect::<Vec<_>>() };
+        Self::new(
+            [
+                [1., tau, 0.], [-1., tau, 0.], [0., 1., tau],
+                [0., -1., tau], [tau, 0., 1.], [-tau, 0., 1.],
+            ].into_iter().map(scale).collect(),
+            [
+                [1., -1./tau, 0.], [-1., -1./tau, 0.], [0., 1., -1./tau],
+                [0., -1., -1./tau], [-1./tau, 0., 1.], [1./tau, 0., 1.],
+            ].into_iter().map(scale).collect(),
+            Window::Hypercube,
+        ).with_offset(vec![0.01234, 0.00567, 0.00311])
+    }
+
+    fn project(point: &[i32], basis: &[Vec<f32>]) -> Vec<f32> {
+        let mut out = vec![0.; basis[0].len()];
+        for (n, v) in point.iter().zip(basis) {
+            for (o, x) in out.iter_mut().zip(v) {
+                *o += *n as f32 * x;
+            }
+        }
+        out
+    }
+
+    /// Slabs bounding the window. The projected hypercube is a zonotope whose faces are spanned by
+    /// all choices of `d-1` perpendicular vectors.
+    fn slabs(&self) -> Vec<(Vec<f32>, f32)> {
+        match &self.window {
+            Window::Hypercube => {
+                let dim = self.perpendicular[0].len();
+                let mut slabs = Vec::new();
+                for subset in subsets(self.perpendicular.len(), dim - 1) {
+                    let vectors = subset.iter().map(|i| &self.perpendicular[*i]).collect::<Vec<_>>();
+                    let normal = normal(&vectors, dim);
+                    if dot(&normal, &normal) < 1e-8 {
+                        continue;
+                    }
+                    let half_width = self.perpendicular.iter().map(|v| dot(&normal, v).abs()).sum::<f32>() / 2.;
+                    slabs.push((normal, half_width));
+                }
+                slabs
+            },
+            Window::Ball(_) => Vec::new(),
+            Window::Polytope(slabs) => slabs.clone(),
+        }
+    }
+
+    /// Generate the patch of the tiling within `radius` of the origin in physical space. Sites are
+    /// found by a breadth-first search through hyperlattice neighbors, starting at the origin.
+    pub fn generate(&self, radius: f32, rule: BondRule) -> Tiling {
+        let slabs = self.slabs();
+        let accept = |point: &[i32]| {
+            let physical = Self::project(point, &self.physical);
+            let perpendicular = Self::project(point, &self.perpendicular).iter().zip(&self.offset)
+                .map(|(x, o)| x + o).collect::<Vec<_>>();
+            let inside = match self.window {
+                Window::Ball(r) => dot(&perpendicular, &perpendicular) < r * r,
+                _ => slabs.iter().all(|(normal, half_width)| dot(normal, &perpendicular).abs() < *half_width),
+            };
+            if inside && dot(&physical, &physical) <= radius * radius {
+                Some(Site { hyper: point.to_vec(), physical, perpendicular })
+            } else {
+                None
+            }
+        };
+
+        let dim = self.physical.len();
+        let mut indices = HashMap::new();
+        let mut sites = Vec::new();
+        let mut hyper_bonds = Vec::new();
+        let mut queue = VecDeque::new();
+        if let Some(site) = accept(&vec![0; dim]) {
+            indices.insert(site.hyper.clone(), 0);
+            sites.push(site);
+            queue.push_back(0);
+        }
+        while let Some(site_index) = queue.pop_front() {
+            for axis in 0..dim {
+                for step in [-1, 1] {
+                    let mut point = sites[site_index].hyper.clone();
+                    point[axis] += step;
+                    let neighbor_index = match indices.get(&point) {
+                        Some(i) => *i,
+                        None => match accept(&point) {
+                            Some(site) => {
+                                indices.insert(point, sites.len());
+                                sites.push(site);
+                                queue.push_back(sites.len() - 1);
+                                sites.len() - 1
+                            },
+                            None => continue,
+                        }
+                    };
+                    if site_index < neighbor_index {
+                        hyper_bonds.push((site_index, neighbor_index));
+                    }
+                }
+            }
+        }
+
+        let bonds = match rule {
+            BondRule::Hyperlattice => hyper_bonds,
+            BondRule::Distance(min, max) => Self::distance_bonds(&sites, min, max),
+        };
+        Tiling { sites, bonds }
+    }
+
+    /// Bond all pairs of sites with physical distance in `(min, max]` using a cell list
+    fn distance_bonds(sites: &[Site], min: f32, max: f32) -> Vec<(usize, usize)> {
+        let cell = |x: &[f32]| { x.iter().map(|c| (c / max).floor() as i64).collect::<Vec<_>>() };
+        let mut cells: HashMap<Vec<i64>, Vec<usize>> = HashMap::new();
+        for (i, site) in sites.iter().enumerate() {
+            cells.entry(cell(&site.physical)).or_default().push(i);
+        }
+
+        let dim = sites.first().map_or(0, |s| s.physical.len());
+        let mut bonds = Vec::new();
+        for (i, site) in sites.iter().enumerate() {
+            let home = cell(&site.physical);
+            for shift in 0..3usize.pow(dim as u32) {
+                let key = home.iter().enumerate()
+                    .map(|(k, c)| c + (shift / 3usize.pow(k as u32) % 3) as i64 - 1)
+                    .collect::<Vec<_>>();
+                for j in cells.get(&key).into_iter().flatten() {
+                    if *j <= i { continue; }
+                    let d2 = site.physical.iter().zip(&sites[*j].physical).map(|(a, b)| (a - b) * (a - b)).sum::<f32>();
+                    if d2 > min * min && d2 <= max * max {
+                        bonds.push((i, *j));
+                    }
+                }
+            }
+        }
+        bonds
+    }
+}
+
+impl Tiling {
+    /// Neighbor function for `Lattice::new` with every bond given coupling `j`
+    pub fn neighbor_fn(&self, j: f32) -> (usize, impl Fn(usize) -> Vec<(usize, f32)>) {
+        let mut adjacency = vec![Vec::new(); self.sites.len()];
+        for (a, b) in &self.bonds {
+            adjacency[*a].push(*b);
+            adjacency[*b].push(*a);
+        }
+        (
+            self.sites.len(),
+            move |site: usize| { adjacency[site].iter().map(|neighbor| (*neighbor, j)).collect() }
+        )
+    }
+
+    /// Geometry file contents, with every bond given the default coupling `j`
+    pub fn geometry(&self, j: f32, provenance: &str) -> Geometry {
+        Geometry::new(
+            self.sites.len(),
+            self.bonds.iter().map(|(a, b)| (*a, *b, 0)).collect(),
+            vec![("edge".to_owned(), j)],
+            Boundary::Open,
+            provenance,
+        ).with_positions(self.sites.iter().map(|site| site.physical.clone()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(tiling: &Tiling, (a, b): (usize, usize)) -> f32 {
+        let (a, b) = (&tiling.sites[a].physical, &tiling.sites[b].physical);
+        a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>().sqrt()
+    }
+
+    /// Degrees of the sites further than `margin` inside the patch of `radius`
+    fn interior_degrees(tiling: &Tiling, radius: f32, margin: f32) -> Vec<usize> {
+        let mut degrees = vec![0; tiling.sites.len()];
+        for (a, b) in &tiling.bonds {
+            degrees[*a] += 1;
+            degrees[*b] += 1;
+        }
+        tiling.sites.iter().zip(degrees)
+            .filter(|(site, _)| dot(&site.physical, &site.physical).sqrt() < radius - margin)
+            .map(|(_, degree)| degree).collect()
+    }
+
+    #[test]
+    fn penrose_vertices_have_three_to_seven_unit_edges() {
+        let tiling = CutProject::penrose().generate(15., BondRule::Hyperlattice);
+        let degrees = interior_degrees(&tiling, 15., 3.);
+        assert_eq!(degrees.iter().min(), Some(&3));
+        assert_eq!(degrees.iter().max(), Some(&7));
+        assert!(tiling.bonds.iter().all(|bond| (distance(&tiling, *bond) - 1.).abs() < 1e-4));
+    }
+
+    #[test]
+    fn ammann_beenker_vertices_have_three_to_eight_edges() {
+        let tiling = CutProject::ammann_beenker().generate(15., BondRule::Hyperlattice);
+        let degrees = interior_degrees(&tiling, 15., 3.);
+        assert_eq!(degrees.iter().min(), Some(&3));
+        assert_eq!(degrees.iter().max(), Some(&8));
+    }
+
+    #[test]
+    fn fibonacci_chain_has_long_and_short_bonds_in_the_golden_ratio() {
+        let tiling = CutProject::fibonacci_chain().generate(200., BondRule::Hyperlattice);
+        assert!(interior_degrees(&tiling, 200., 5.).iter().all(|degree| *degree == 2));
+        let lengths = tiling.bonds.iter().map(|bond| distance(&tiling, *bond)).collect::<Vec<_>>();
+        let (short, long) = lengths.iter().fold((f32::INFINITY, 0f32), |(s, l), x| (s.min(*x), l.max(*x)));
+        let tau = (1. + 5f32.sqrt()) / 2.;
+        assert!((long / short - tau).abs() < 1e-4);
+        let num_long = lengths.iter().filter(|x| (*x - long).abs() < 1e-4).count() as f32;
+        let num_short = lengths.iter().filter(|x| (*x - short).abs() < 1e-4).count() as f32;
+        assert_eq!(num_long + num_short, lengths.len() as f32);
+        assert!((num_long / num_short - tau).abs() < 0.05);
+    }
+
+    #[test]
+    fn sites_are_distinct_and_within_the_radius() {
+        let tiling = CutProject::icosahedral().generate(5., BondRule::Hyperlattice);
+        let distinct = tiling.sites.iter().map(|site| site.hyper.clone()).collect::<std::collections::HashSet<_>>();
+        assert_eq!(distinct.len(), tiling.sites.len());
+        assert!(tiling.sites.iter().all(|site| dot(&site.physical, &site.physical) <= 25.));
+    }
+
+    #[test]
+    fn distance_rule_finds_every_edge() {
+        let edges = CutProject::penrose().generate(10., BondRule::Hyperlattice);
+        let close = CutProject::penrose().generate(10., BondRule::Distance(0.99, 1.01));
+        let found = close.bonds.iter().collect::<std::collections::HashSet<_>>();
+        assert!(edges.bonds.iter().all(|bond| found.contains(bond)));
+        assert!(close.bonds.iter().all(|bond| (distance(&close, *bond) - 1.).abs() < 0.011));
+    }
+}
@@ -1,23 +1,32 @@
-use crate::spin::{Spin, SmallVec};
-use crate::lattice::Report;
+use crate::sampler::Sampler;
+use crate::snapshot::{self, Snapshots};
+use crate::validate::LoadError;
 
-use std::fmt::Display;
-use rand::random;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
-const CLUSTER_STACK_MAX_SIZE: usize = 256_000;
-const BURN_IN: usize = 4;
-const RUNS_PER_TRIAL: usize = 8;
 const SPIN_BC: bool = true;
 const EPSILON : f32 = 1e-5;
 
 pub struct QLattice {
     length_three: f32,
     data: Vec<Vec<f32>>,
-    neighbors: Vec<SmallVec<(usize, f32)>>,
-    cluster_stack: [(usize, f32); CLUSTER_STACK_MAX_SIZE],
+    /// Neighbors of every site stored contiguously. Those of site `i` are at
+    /// `neighbor_offsets[i]..neighbor_offsets[i+1]`.
+    neighbors: Vec<(usize, f32)>,
+    neighbor_offsets: Vec<usize>,
+    /// Column segments waiting to grow the cluster. A site can join at several heights, so it grows
+    /// as needed.
+    cluster_stack: Vec<(usize, f32)>,
+    snapshots: Option<Snapshots>,
+    series: Option<String>,
+    rng: ChaCha8Rng,
+    /// Clusters flipped, and column segments in them, since the lattice was made
+    num_clusters: u64,
+    num_cluster_sites: u64,
 }
 
-fn verify_sorted(l: &Vec<f32>) -> bool {
+fn verify_sorted(l: &[f32]) -> bool {
     let mut last = None;
     for i in l {
         if let Some(las) = last {
@@ -31,76 +40,28 @@ fn verify_sorted(l: &Vec<f32>) -> bool {
 }
 
 impl QLattice {
-    /// Create a new lattice from a function that generates neighbors.
-    pub fn new(num_sites: usize, neighbor_func: &impl Fn(usize) -> SmallVec<(usize, f32)>) -> Self {
-        let mut data = Vec::with_capacity(num_sites);
-        let mut neighbors = Vec::with_capacity(num_sites);
-        let length_three = 2. * (num_sites as f32).sqrt();
-        for site_index in 0..num_sites {
-            data.push(Vec::new());
-            neighbors.push(neighbor_func(site_index));
-        }
-
-        Self {
-            length_three,
-            data,
-            neighbors,
-            cluster_stack: [(0, 0.0); CLUSTER_STACK_MAX_SIZE],
-        }
-    }
-
-    /// Zero out the data set
-    pub fn zero(&mut self) {
-        for item in self.data.iter_mut() {
-            item.clear();
-            // *item = (0..self.length_three as usize).into_iter().map(|_| { random::<f32>() * self.length_three }).collect::<Vec<_>>();
-            item.sort_by(|a,b| a.partial_cmp(b).unwrap() );
-        }
-    }
-
-    /// Same run function as for `Lattice`.
-    pub fn run(&mut self, beta: f32, n_trials: usize, checkpoint: (usize, f32)) -> Report {
-        let mut num_avgs = n_trials - BURN_IN;
-
-        self.evolve(beta, RUNS_PER_TRIAL * BURN_IN);
-        let mut avg_mag = 0.0;
-        let mut avg_mag2 = 0.0;
-        for trial_index in BURN_IN..n_trials {
-            self.evolve(beta, RUNS_PER_TRIAL);
-            let mag = self.magnetization();
-            avg_mag += mag;
-            avg_mag2 += mag * mag;
-
-            if trial_index == checkpoint.0 {
-                if avg_mag / (trial_index - BURN_IN + 1) as f32 > checkpoint.1 {
-                    num_avgs = trial_index - BURN_IN + 1;
-                    break;
-                }
-            }
-        }
-        avg_mag /= num_avgs as f32;
-        avg_mag2 /= num_avgs as f32;
-        let sus = (avg_mag2 - avg_mag*avg_mag) * self.data.len() as f32;
-        {
-            let mut num_lengths = 0.;
-            for item in &self.data {
-                num_lengths += item.len() as f32;
-            }
-            num_lengths /= self.data.len() as f32;
-            println!("Mag {} num_lengths {}", avg_mag, num_lengths);
-        }
-        Report {
-            magnetization: avg_mag,
-            susceptibility: sus * beta,
+    /// Writes the imaginary-time interfaces of every site to `{prefix}-interfaces.npy`, and
+    /// the offsets into it to `{prefix}-offsets.npy`, so that the interfaces of site `i` are
+    /// `interfaces[offsets[i]..offsets[i+1]]`. Each column starts with spin `SPIN_BC` at time zero.
+    pub fn save_snapshot(&self, prefix: &str) -> Result<(), LoadError> {
+        let mut offsets = vec![0i64];
+        let mut interfaces = Vec::new();
+        for item in &self.data {
+            interfaces.extend_from_slice(item);
+            offsets.push(interfaces.len() as i64);
         }
+        snapshot::write_f32(&format!("{}-interfaces.npy", prefix), &[interfaces.len()], &interfaces)?;
+        snapshot::write_i64(&format!("{}-offsets.npy", prefix), &[offsets.len()], &offsets)
     }
 
-    /// Flips exactly one cluster. This code has been optimized to make it branchless. 
-    fn evolve(&mut self, beta: f32, count: usize) {
+    /// Flips `count` clusters and returns the average number of column segments in one as a
+    /// fraction of the lattice. This code has been optimized to make it branchless.
+    fn evolve(&mut self, beta: f32, count: usize) -> f32 {
+        let mut cluster_size = 0;
         for _ in 0..count {
             // 1. Choose random site
-            let start_index = random::<usize>() % self.data.len();
-            let start_height = random::<f32>() * self.length_three;
+            let start_index = self.rng.gen_range(0..self.data.len());
+            let start_height = self.rng.gen::<f32>() * self.length_three;
             
             // Set up the stack
             self.cluster_stack[0] = (start_index, start_height);
@@ -110,6 +71,7 @@ impl QLattice {
             while stack_pointer >= 0 {
                 let (my_index, my_height) = self.cluster_stack[stack_pointer as usize];
                 let mut new_stack_pointer = stack_pointer - 1;
+                cluster_size += 1;
                 let my_column = &mut self.data[my_index];
 
                 // 2. Get dl and dr
@@ -136,7 +98,7 @@ impl QLattice {
                                 }
                             },
                             None => {
-                                let zero_index = if my_column.len() == 0 { None } else { Some(my_column.len() - 1)};
+                                let zero_index = if my_column.is_empty() { None } else { Some(my_column.len() - 1)};
                                 break (my_spin,
                                     my_height - match my_column.last() {
                                         Some(f) => *f,
@@ -152,8 +114,8 @@ impl QLattice {
                 };
 
                 // 3. Flip spins in this column
-                let mut lr = -random::<f32>().ln();
-                let mut ll = -random::<f32>().ln();
+                let mut lr = -self.rng.gen::<f32>().ln();
+                let mut ll = -self.rng.gen::<f32>().ln();
 
                 for (site, height) in &self.cluster_stack[0..stack_pointer as usize] {
                     // Do not allow the flipping of points in the queue
@@ -175,7 +137,7 @@ impl QLattice {
                 } else {
                     // Delete right interface
                     match right_index {
-                        Some(i) => {my_column.remove(i); ()},
+                        Some(i) => { my_column.remove(i); },
                         None => my_column.push(self.length_three)
                     }
                 }
@@ -188,7 +150,7 @@ impl QLattice {
                 } else {
                     // Delete left interface
                     match left_index {
-                        Some(i) => { my_column.remove(i); () },
+                        Some(i) => { my_column.remove(i); },
                         None => my_column.insert(0, 0.)
                     }
                 }
@@ -197,12 +159,12 @@ impl QLattice {
                 let cl = dl.min(ll);
 
                 // 4. Make bridges
-                for (neighbor, strength) in self.neighbors[my_index].iter_mut() {
+                for (neighbor, strength) in self.neighbors[self.neighbor_offsets[my_index]..self.neighbor_offsets[my_index + 1]].iter() {
                     if marked_sites[*neighbor] { continue; }
                     let neighbor_column = &self.data[*neighbor];
                     let mut cluster_done_so_far = 0.;
                     loop {
-                        let lx = -random::<f32>().ln() / (2. * strength * beta);
+                        let lx = -self.rng.gen::<f32>().ln() / (2. * strength * beta);
                         cluster_done_so_far += lx;
                         let neighbor_height = my_height - cl + cluster_done_so_far;
                         if cluster_done_so_far > cl + cr { break; }
@@ -217,27 +179,137 @@ impl QLattice {
                         }
                         if neighbor_spin != my_spin { continue; }
 
-                        if new_stack_pointer + 1 >= CLUSTER_STACK_MAX_SIZE as i32 {
-                            break;
-                        }
-
                         // Write the neighbor height into the stack
-                        self.cluster_stack[(new_stack_pointer + 1) as usize] = (*neighbor, neighbor_height);
+                        let slot = (new_stack_pointer + 1) as usize;
+                        if slot < self.cluster_stack.len() {
+                            self.cluster_stack[slot] = (*neighbor, neighbor_height);
+                        } else {
+                            self.cluster_stack.push((*neighbor, neighbor_height));
+                        }
                         marked_sites[*neighbor] = true;
                         new_stack_pointer += 1;
                     }
                 }
-                if new_stack_pointer + 1 >= CLUSTER_STACK_MAX_SIZE as i32 {
-                    println!("Stack exceeded");
-                    break;
-                }
                 stack_pointer = new_stack_pointer;
             }
         }
+        self.num_clusters += count as u64;
+        self.num_cluster_sites += cluster_size as u64;
+        cluster_size as f32 / count as f32 / self.data.len() as f32
+    }
+}
+
+impl Sampler for QLattice {
+    const BURN_IN: usize = 4;
+    const RUNS_PER_TRIAL: usize = 8;
+
+    fn new(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>) -> Self {
+        let mut data = Vec::with_capacity(num_sites);
+        let mut neighbors = Vec::new();
+        let mut neighbor_offsets = Vec::with_capacity(num_sites + 1);
+        neighbor_offsets.push(0);
+        let length_three = 2. * (num_sites as f32).sqrt();
+        for site_index in 0..num_sites {
+            data.push(Vec::new());
+            neighbors.extend(neighbor_func(site_index));
+            neighbor_offsets.push(neighbors.len());
+        }
+
+        Self {
+            length_three,
+            data,
+            neighbors,
+            neighbor_offsets,
+            cluster_stack: vec![(0, 0.0); num_sites + 1],
+            snapshots: None,
+            series: None,
+            rng: ChaCha8Rng::from_entropy(),
+            num_clusters: 0,
+            num_cluster_sites: 0,
+        }
+    }
+
+    fn model() -> String {
+        "tim".to_owned()
+    }
+
+    fn num_sites(&self) -> usize {
+        self.data.len()
+    }
+
+    fn rng(&self) -> &ChaCha8Rng {
+        &self.rng
+    }
+
+    fn set_rng(&mut self, rng: ChaCha8Rng) {
+        self.rng = rng;
+    }
+
+    fn cluster_counts(&self) -> (u64, u64) {
+        (self.num_clusters, self.num_cluster_sites)
+    }
+
+    fn zero(&mut self) {
+        for item in self.data.iter_mut() {
+            item.clear();
+            // *item = (0..self.length_three as usize).into_iter().map(|_| { self.rng.gen::<f32>() * self.length_three }).collect::<Vec<_>>();
+            item.sort_by(|a,b| a.partial_cmp(b).unwrap() );
+        }
+    }
+
+    /// Counts the column segments of a cluster as its sites
+    fn step(&mut self, beta: f32, count: usize) -> f32 {
+        self.evolve(beta, count)
+    }
+
+    fn set_series(&mut self, prefix: Option<&str>) {
+        self.series = prefix.map(|p| p.to_owned());
+    }
+
+    fn series(&self) -> Option<&str> {
+        self.series.as_deref()
+    }
+
+    fn maybe_snapshot(&self, beta: f32, measurement: usize, last: bool) -> Result<(), LoadError> {
+        match &self.snapshots {
+            Some(snapshots) if snapshots.wants(beta, measurement, last) =>
+                self.save_snapshot(&snapshots.filename(beta, measurement)),
+            _ => Ok(()),
+        }
+    }
+
+    fn set_snapshots(&mut self, snapshots: Option<Snapshots>) {
+        self.snapshots = snapshots;
+    }
+
+    fn load_snapshot(&mut self, prefix: &str) -> Result<(), LoadError> {
+        let interfaces_file = format!("{}-interfaces.npy", prefix);
+        let offsets_file = format!("{}-offsets.npy", prefix);
+        let (_, interfaces) = snapshot::read_f32(&interfaces_file)?;
+        let (_, offsets) = snapshot::read_i64(&offsets_file)?;
+        let parse_error = |file: &str, message: String| LoadError::Parse { file: file.to_owned(), line: None, message };
+        if offsets.len() != self.data.len() + 1 {
+            return Err(parse_error(&offsets_file, format!("{} offsets do not match {} sites", offsets.len(), self.data.len())));
+        }
+        if offsets.windows(2).any(|w| w[0] < 0 || w[1] < w[0]) || offsets[self.data.len()] as usize != interfaces.len() {
+            return Err(parse_error(&offsets_file, "offsets are not increasing or do not cover the interfaces".to_owned()));
+        }
+        for (i, item) in self.data.iter_mut().enumerate() {
+            let column = &interfaces[offsets[i] as usize..offsets[i + 1] as usize];
+            if !verify_sorted(column) || column.iter().any(|h| *h < 0. || *h > self.length_three) {
+                return Err(parse_error(&interfaces_file, format!("interfaces of site {} are unsorted or out of range", i)));
+            }
+            item.clear();
+            item.extend_from_slice(column);
+        }
+        Ok(())
+    }
+
+    fn magnetization_vector(&self) -> Vec<f32> {
+        vec![self.magnetization()]
     }
 
-    /// Computes the magnetization of the crystal
-    pub fn magnetization(&self) -> f32 {
+    fn magnetization(&self) -> f32 {
         let mut frac = 0.0;
         for item in &self.data {
             let mut spin_up = SPIN_BC;
@@ -259,4 +331,23 @@ impl QLattice {
         }
         frac.abs() / self.data.len() as f32 / self.length_three
     }
-}
\ No newline at end of file
+
+    /// Not measured
+    fn energy(&self) -> f32 {
+        f32::NAN
+    }
+
+    /// The imaginary-time interfaces of every site
+    fn configuration(&self) -> Vec<Vec<f32>> {
+        self.data.clone()
+    }
+
+    fn set_configuration(&mut self, configuration: &[Vec<f32>]) {
+        assert_eq!(configuration.len(), self.data.len(), "Every site needs its interfaces");
+        for (i, (item, column)) in self.data.iter_mut().zip(configuration).enumerate() {
+            assert!(verify_sorted(column) && column.iter().all(|h| *h >= 0. && *h <= self.length_three),
+                "Interfaces of site {} are unsorted or out of range", i);
+            item.clone_from(column);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! SVG pictures of spin configurations on lattices with positions
+
+use crate::geometry::{Boundary, Geometry};
+use crate::spin::Spin;
+use crate::validate::LoadError;
+
+/// Width of the picture in pixels. The height follows from the aspect ratio of the lattice.
+const WIDTH: f32 = 1000.;
+const MARGIN: f32 = 20.;
+/// Colors of the bond kinds, in order, repeating if there are more kinds
+const BOND_COLORS: [&str; 6] = ["#7f7f7f", "#e69f00", "#009e73", "#cc79a7", "#56b4e9", "#d55e00"];
+const UP_COLOR: &str = "#c0392b";
+const DOWN_COLOR: &str = "#2e86c1";
+const EMPTY_COLOR: &str = "#ffffff";
+
+/// A drawing of a geometry, optionally with spins and one highlighted cluster
+pub struct Picture<'a> {
+    geometry: &'a Geometry,
+    site_colors: Option<Vec<String>>,
+    cluster: Vec<usize>,
+}
+
+/// Color of a spin. Ising spins are colored by sign, vector spins by the angle in the xy-plane,
+/// with Heisenberg spins lighter the further they point along z.
+fn spin_color<S: Spin>(spin: &S) -> String {
+    let components = spin.components();
+    match components[..] {
+        [s] => (if s < 0. { DOWN_COLOR } else { UP_COLOR }).to_owned(),
+        [x, y, ref rest @ ..] => {
+            let hue = y.atan2(x).to_degrees().rem_euclid(360.);
+            let lightness = 50. + 35. * rest.first().copied().unwrap_or(0.);
+            format!("hsl({:.0},75%,{:.0}%)", hue, lightness)
+        }
+        [] => EMPTY_COLOR.to_owned(),
+    }
+}
+
+impl<'a> Picture<'a> {
+    /// Draw the sites and bonds of `geometry`, which must have positions. Only the first two
+    /// coordinates are used.
+    pub fn new(geometry: &'a Geometry) -> Self {
+        assert!(geometry.dimension() >= 2, "Drawing needs positions in at least two dimensions");
+        Self { geometry, site_colors: None, cluster: Vec::new() }
+    }
+
+    /// Color every site by its spin
+    pub fn with_spins<S: Spin>(mut self, spins: &[S]) -> Self {
+        assert_eq!(spins.len(), self.geometry.num_sites(), "Every site needs a spin");
+        self.site_colors = Some(spins.iter().map(spin_color).collect());
+        self
+    }
+
+    /// Outline the sites of a cluster, as returned by `Lattice::wolff_cluster`, and the bonds between them
+    pub fn with_cluster(mut self, sites: &[usize]) -> Self {
+        self.cluster = sites.to_vec();
+        self
+    }
+
+    pub fn to_svg(&self) -> String {
+        let positions = &self.geometry.positions;
+        let (mut min, mut max) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+        for p in positions {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+        let scale = (WIDTH - 2. * MARGIN) / (max[0] - min[0]).max(max[1] - min[1]).max(f32::EPSILON);
+        let height = (max[1] - min[1]) * scale + 2. * MARGIN;
+        let point = |site: usize| {
+            let p = &positions[site];
+            (MARGIN + (p[0] - min[0]) * scale, height - MARGIN - (p[1] - min[1]) * scale)
+        };
+
+        // Bonds across a periodic boundary would cross the whole picture, so leave them out
+        let wraps = |a: usize, b: usize| match &self.geometry.boundary {
+            Boundary::Open => false,
+            Boundary::Periodic(lengths) => lengths.iter().zip(positions[a].iter().zip(&positions[b]))
+                .any(|(length, (x, y))| (x - y).abs() > length / 2.),
+        };
+        let mut lengths = self.geometry.bonds.iter()
+            .filter(|(a, b, _)| !wraps(*a, *b))
+            .map(|(a, b, _)| {
+                let (p, q) = (point(*a), point(*b));
+                ((p.0 - q.0).powi(2) + (p.1 - q.1).powi(2)).sqrt()
+            })
+            .collect::<Vec<_>>();
+        lengths.sort_by(f32::total_cmp);
+        let bond_length = lengths.get(lengths.len() / 2).copied().unwrap_or(10.);
+        let radius = 0.25 * bond_length;
+        let stroke = 0.08 * bond_length;
+
+        let mut in_cluster = vec![false; self.geometry.num_sites()];
+        for site in &self.cluster {
+            in_cluster[*site] = true;
+        }
+
+        let mut out = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\" viewBox=\"0 0 {:.0} {:.0}\">\n",
+            WIDTH, height, WIDTH, height);
+        out += &format!("<title>{}</title>\n", self.geometry.provenance.replace('&', "&amp;").replace('<', "&lt;"));
+        out += "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>\n";
+        out += &format!("<g stroke-width=\"{:.2}\">\n", stroke);
+        for (a, b, kind) in &self.geometry.bonds {
+            if wraps(*a, *b) { continue; }
+            let (p, q) = (point(*a), point(*b));
+            out += &format!("<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\"/>\n",
+                p.0, p.1, q.0, q.1, BOND_COLORS[kind % BOND_COLORS.len()]);
+        }
+        out += "</g>\n";
+
+        if !self.cluster.is_empty() {
+            out += &format!("<g stroke=\"black\" stroke-width=\"{:.2}\">\n", 3. * stroke);
+            for (a, b, _) in &self.geometry.bonds {
+                if !in_cluster[*a] || !in_cluster[*b] || wraps(*a, *b) { continue; }
+                let (p, q) = (point(*a), point(*b));
+                out += &format!("<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\"/>\n", p.0, p.1, q.0, q.1);
+            }
+            out += "</g>\n";
+        }
+
+        out += &format!("<g stroke=\"black\" stroke-width=\"{:.2}\">\n", stroke);
+        for site in 0..self.geometry.num_sites() {
+            let (x, y) = point(site);
+            let fill = self.site_colors.as_ref().map_or(EMPTY_COLOR, |colors| &colors[site]);
+            let outline = if in_cluster[site] { format!(" stroke-width=\"{:.2}\"", 3. * stroke) } else { String::new() };
+            out += &format!("<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"{}/>\n", x, y, radius, fill, outline);
+        }
+        out += "</g>\n</svg>\n";
+        out
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), LoadError> {
+        std::fs::write(filename, self.to_svg()).map_err(|source| LoadError::Io { file: filename.to_owned(), source })
+    }
+}
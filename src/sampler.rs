@@ -0,0 +1,149 @@
+//! What the drivers need of a model: a lattice of sites that is reset, run at a beta by cluster
+//! flips, and measured. Both the classical `Lattice` and the quantum `QLattice` are samplers, so
+//! every driver runs either one.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::adjacency::Adjacency;
+use crate::geometry::Geometry;
+use crate::lattice::{Report, RunState};
+use crate::snapshot::{Snapshots, TableWriter};
+use crate::validate::LoadError;
+
+/// Measurements between calls to the `save` callback of `run_from`
+pub const SAVE_INTERVAL: usize = 1000;
+
+pub trait Sampler: Sized {
+    /// Measurements discarded at the start of a run
+    const BURN_IN: usize;
+    /// Cluster flips between measurements
+    const RUNS_PER_TRIAL: usize;
+
+    /// Create a new lattice from a function that generates neighbors.
+    fn new(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>) -> Self;
+
+    /// Create a new lattice from a shared adjacency, where a bond of kind `k` has coupling `couplings[k]`
+    fn from_adjacency(adjacency: &Adjacency, couplings: &[f32]) -> Self {
+        Self::new(adjacency.num_sites(), &adjacency.neighbor_fn(couplings))
+    }
+
+    /// Create a new lattice from a geometry file, with its default couplings
+    fn from_geometry(geometry: &Geometry) -> Self {
+        Self::from_adjacency(&geometry.adjacency(), &geometry.couplings())
+    }
+
+    /// Name of the model, as given to `--spin`
+    fn model() -> String;
+
+    fn num_sites(&self) -> usize;
+
+    fn rng(&self) -> &ChaCha8Rng;
+
+    fn set_rng(&mut self, rng: ChaCha8Rng);
+
+    /// Restart the random number generator from `seed`. A lattice seeded and zeroed before a sweep
+    /// gives the same results every time.
+    fn seed(&mut self, seed: u64) {
+        self.set_rng(ChaCha8Rng::seed_from_u64(seed));
+    }
+
+    /// Reset the configuration to the start of a run
+    fn zero(&mut self);
+
+    /// Flips `count` clusters at `beta` and returns their mean size as a fraction of the lattice
+    fn step(&mut self, beta: f32, count: usize) -> f32;
+
+    /// Run cluster flips and measures the magnetization and energy every `RUNS_PER_TRIAL` flips. Returns the observables after `n_trials` measurements, or after `checkpoint.0` measurements if the mean magnetization is then over `checkpoint.1`. The checkpoint is a runtime-improvement feature. To turn it off, set `checkpoint.1` greater than 1. Fails only if a time series or snapshot cannot be written.
+    fn run(&mut self, beta: f32, n_trials: usize, checkpoint: (usize, f32)) -> Result<Report, LoadError> {
+        self.run_from(&mut RunState::new(beta), n_trials, checkpoint, |_, _| Ok(()))
+    }
+
+    /// Same as `run`, but continues from the measurements in `state`. Calls `save` after burn-in and
+    /// every `SAVE_INTERVAL` measurements; the lattice, its random number generator and `state` at
+    /// that point are enough to continue the run exactly. Errors from `save` stop the run.
+    fn run_from(&mut self, state: &mut RunState, n_trials: usize, checkpoint: (usize, f32),
+        mut save: impl FnMut(&Self, &RunState) -> Result<(), LoadError>) -> Result<Report, LoadError> {
+        let beta = state.beta;
+        let mut series = self.series().map(|prefix| {
+            let filename = format!("{}-beta{:.6}.npy", prefix, beta);
+            let columns = self.magnetization_vector().len() + 2;
+            if state.magnetizations.is_empty() {
+                TableWriter::create(&filename, columns)
+            } else {
+                TableWriter::resume(&filename, columns, state.magnetizations.len())
+            }
+        }).transpose()?;
+        if !state.burned_in {
+            self.step(beta, Self::RUNS_PER_TRIAL * Self::BURN_IN);
+            state.burned_in = true;
+            save(self, state)?;
+        }
+        for trial_index in Self::BURN_IN + state.magnetizations.len()..n_trials {
+            let cluster_size = self.step(beta, Self::RUNS_PER_TRIAL);
+            let mag = self.magnetization();
+            let energy = self.energy();
+            state.magnetizations.push(mag);
+            state.energies.push(energy);
+            self.maybe_snapshot(beta, state.magnetizations.len(), false)?;
+            if let Some(series) = &mut series {
+                let mut row = self.magnetization_vector();
+                row.extend([energy, cluster_size]);
+                series.push(&row)?;
+            }
+
+            if trial_index == checkpoint.0
+                && state.magnetizations.iter().sum::<f32>() / (trial_index - Self::BURN_IN + 1) as f32 > checkpoint.1 {
+                break;
+            }
+            if state.magnetizations.len().is_multiple_of(SAVE_INTERVAL) {
+                if let Some(series) = &mut series {
+                    series.flush()?;
+                }
+                save(self, state)?;
+            }
+        }
+        if let Some(series) = &mut series {
+            series.flush()?;
+        }
+        self.maybe_snapshot(beta, state.magnetizations.len(), true)?;
+        Ok(Report::from_measurements(&state.magnetizations, &state.energies, beta, self.num_sites()))
+    }
+
+    /// Stream every measurement of `run` to `{prefix}-beta{beta}.npy`, an `f32` array with one row
+    /// per measurement holding the components of the magnetization, the energy per site and the
+    /// mean cluster size as a fraction of the lattice. `None` stops it.
+    fn set_series(&mut self, prefix: Option<&str>);
+
+    /// Prefix of the time series files, if they are being written
+    fn series(&self) -> Option<&str>;
+
+    /// Writes a snapshot of the configuration if the snapshot settings want one after
+    /// `measurement` measurements at `beta`, or at the end of the run if `last`
+    fn maybe_snapshot(&self, beta: f32, measurement: usize, last: bool) -> Result<(), LoadError>;
+
+    /// Write snapshots during `run` as described by `snapshots`, or stop writing them with `None`
+    fn set_snapshots(&mut self, snapshots: Option<Snapshots>);
+
+    /// Sets the configuration from the snapshot `name`, as `maybe_snapshot` writes it, so that a run
+    /// starts warm instead of from `zero`
+    fn load_snapshot(&mut self, name: &str) -> Result<(), LoadError>;
+
+    /// Components of the magnetization per site
+    fn magnetization_vector(&self) -> Vec<f32>;
+
+    /// Magnetization per site
+    fn magnetization(&self) -> f32;
+
+    /// Energy per site, or NaN if the model does not measure it
+    fn energy(&self) -> f32;
+
+    /// Number of clusters flipped so far, and the total number of sites in them
+    fn cluster_counts(&self) -> (u64, u64);
+
+    /// State of every site as numbers, as saved in checkpoints
+    fn configuration(&self) -> Vec<Vec<f32>>;
+
+    /// Sets the state of every site from `configuration`
+    fn set_configuration(&mut self, configuration: &[Vec<f32>]);
+}
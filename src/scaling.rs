@@ -0,0 +1,275 @@
+//! Finite-size scaling of sweeps on lattices of increasing size. Tc is where the Binder cumulants
+//! of successive sizes cross, and at Tc
+//!
+//! ```text
+//! χ_max ~ L^(γ/ν),   m ~ L^(-β/ν),   dU4/dT ~ L^(1/ν)
+//! ```
+//!
+//! with `L = N^(1/d)` for `N` sites in `d` dimensions.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::analysis::{column, interpolate, mean_crossing, redraw, spread, Estimate, NUM_RESAMPLES};
+use crate::drivers::{build_geometry, finished, output_name, sweep, RunError};
+use crate::jobs;
+use crate::log;
+use crate::options::{Command, Options};
+use crate::output::{write_table, Data, Run};
+use crate::sampler::Sampler;
+use crate::validate::LoadError;
+
+/// Half-width of the temperature window around Tc used in data collapse, relative to Tc
+pub const COLLAPSE_WINDOW: f32 = 0.1;
+
+/// Columns of the scaling table, in order
+pub const COLUMNS: [&str; 10] = [
+    "length", "num_sites",
+    "susceptibility_max", "susceptibility_max_error",
+    "magnetization", "magnetization_error",
+    "binder", "binder_error",
+    "binder_slope", "binder_slope_error",
+];
+
+/// Sweep of one size
+pub struct Size<'a> {
+    /// Linear size `N^(1/d)`
+    pub length: f32,
+    pub num_sites: usize,
+    pub data: &'a Data,
+}
+
+/// A value with its uncertainty
+#[derive(Clone, Copy, Debug)]
+pub struct Fitted {
+    pub value: f32,
+    pub error: f32,
+}
+
+/// Observables of one size at Tc
+pub struct AtCritical {
+    pub length: f32,
+    pub num_sites: usize,
+    pub susceptibility_max: Fitted,
+    pub magnetization: Fitted,
+    pub binder: Fitted,
+    /// `-dU4/dT`
+    pub binder_slope: Fitted,
+}
+
+/// Exponents and Tc that collapse the susceptibilities of every size onto one curve
+pub struct Collapse {
+    pub tc: f32,
+    pub inverse_nu: f32,
+    pub gamma_nu: f32,
+    /// Mean squared distance between the scaled curves in units of their errors, near 1 for a
+    /// good collapse
+    pub quality: f32,
+}
+
+pub struct Scaling {
+    pub tc: Estimate,
+    pub gamma_nu: Fitted,
+    pub beta_nu: Fitted,
+    pub inverse_nu: Fitted,
+    pub sizes: Vec<AtCritical>,
+    pub collapse: Collapse,
+}
+
+/// Slope of the least-squares line through `points`
+fn line_slope(points: &[(f32, f32)]) -> f32 {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0 as f64).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1 as f64).sum::<f64>() / n;
+    let sxy = points.iter().map(|p| (p.0 as f64 - mean_x) * (p.1 as f64 - mean_y)).sum::<f64>();
+    let sxx = points.iter().map(|p| (p.0 as f64 - mean_x).powi(2)).sum::<f64>();
+    (sxy / sxx) as f32
+}
+
+/// Slope of the piecewise linear `points` at `x`
+fn slope_at(points: &[(f32, f32)], x: f32) -> Option<f32> {
+    let w = points.windows(2).find(|w| w[0].0 <= x && x <= w[1].0 && w[1].0 > w[0].0)?;
+    Some((w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+}
+
+/// Tc, γ/ν, β/ν and 1/ν, then the susceptibility maximum, magnetization, Binder cumulant and
+/// Binder slope at Tc of each size, from the susceptibility, magnetization and Binder curves of
+/// each size in turn
+fn measure(lengths: &[f32], curves: &[Vec<(f32, f32)>]) -> Option<Vec<f32>> {
+    let binders = curves.iter().skip(2).step_by(3).cloned().collect::<Vec<_>>();
+    let tc = mean_crossing(&binders)?;
+    let mut per_size = Vec::new();
+    for chunk in curves.chunks(3) {
+        let chi_max = chunk[0].iter().map(|p| p.1).fold(f32::NAN, f32::max);
+        per_size.push([chi_max, interpolate(&chunk[1], tc)?, interpolate(&chunk[2], tc)?, -slope_at(&chunk[2], tc)?]);
+    }
+    let fit = |column: usize| {
+        line_slope(&lengths.iter().zip(&per_size).map(|(l, x)| (l.ln(), x[column].abs().ln())).collect::<Vec<_>>())
+    };
+    let mut out = vec![tc, fit(0), -fit(1), fit(3)];
+    out.extend(per_size.iter().flatten());
+    out.iter().all(|x| x.is_finite()).then_some(out)
+}
+
+/// How well `(tc, 1/ν, γ/ν)` collapses the susceptibility `curves` of sizes `lengths`
+pub fn collapse_quality(lengths: &[f32], curves: &[Vec<(f32, f32, f32)>], tc: f32, inverse_nu: f32, gamma_nu: f32) -> f32 {
+    let scaled = lengths.iter().zip(curves).map(|(l, curve)| {
+        curve.iter().filter(|p| (p.0 - tc).abs() <= COLLAPSE_WINDOW * tc).map(|&(t, y, e)| {
+            let factor = l.powf(-gamma_nu);
+            ((t - tc) * l.powf(inverse_nu), y * factor, e * factor)
+        }).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+
+    let mut total = 0.;
+    let mut count = 0;
+    for (i, points) in scaled.iter().enumerate() {
+        for (j, other) in scaled.iter().enumerate() {
+            if i == j { continue; }
+            let values = other.iter().map(|p| (p.0, p.1)).collect::<Vec<_>>();
+            let errors = other.iter().map(|p| (p.0, p.2)).collect::<Vec<_>>();
+            for &(x, y, e) in points {
+                let (Some(y_other), Some(e_other)) = (interpolate(&values, x), interpolate(&errors, x)) else { continue };
+                total += (y - y_other).powi(2) / (e * e + e_other * e_other).max(f32::MIN_POSITIVE);
+                count += 1;
+            }
+        }
+    }
+    if count == 0 { f32::INFINITY } else { total / count as f32 }
+}
+
+/// Compass search for the `(tc, 1/ν, γ/ν)` of best collapse, starting from `start`
+fn best_collapse(lengths: &[f32], curves: &[Vec<(f32, f32, f32)>], start: [f32; 3]) -> Collapse {
+    let quality = |x: [f32; 3]| collapse_quality(lengths, curves, x[0], x[1], x[2]);
+    let mut best = start;
+    let mut best_quality = quality(best);
+    let mut steps = [0.01 * start[0], 0.1, 0.1];
+    for _ in 0..500 {
+        if steps[0] < 1e-5 * start[0] { break; }
+        let mut improved = false;
+        for axis in 0..3 {
+            for sign in [-1., 1.] {
+                let mut x = best;
+                x[axis] += sign * steps[axis];
+                let q = quality(x);
+                if q < best_quality {
+                    (best, best_quality, improved) = (x, q, true);
+                }
+            }
+        }
+        if !improved {
+            steps = steps.map(|s| s / 2.);
+        }
+    }
+    Collapse { tc: best[0], inverse_nu: best[1], gamma_nu: best[2], quality: best_quality }
+}
+
+/// Scaling analysis of sweeps of one model on lattices of increasing size. Uncertainties are
+/// bootstrapped from the errors of the sweeps.
+pub fn fit(sizes: &[Size]) -> Option<Scaling> {
+    if sizes.len() < 2 {
+        return None;
+    }
+    let lengths = sizes.iter().map(|s| s.length).collect::<Vec<_>>();
+    let curves = sizes.iter().flat_map(|s| [
+        column(s.data, |r| (r.susceptibility, r.susceptibility_error)),
+        column(s.data, |r| (r.magnetization, r.magnetization_error)),
+        column(s.data, |r| (r.binder, r.binder_error)),
+    ]).collect::<Vec<_>>();
+
+    let central = measure(&lengths, &curves.iter().map(|c| c.iter().map(|&(x, y, _)| (x, y)).collect()).collect::<Vec<_>>())?;
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let resamples = (0..NUM_RESAMPLES)
+        .filter_map(|_| measure(&lengths, &curves.iter().map(|c| redraw(c, &mut rng)).collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    let fitted = |i: usize| Fitted {
+        value: central[i],
+        error: spread(&resamples.iter().map(|r| r[i]).collect::<Vec<_>>()),
+    };
+
+    let susceptibilities = curves.iter().step_by(3).cloned().collect::<Vec<_>>();
+    Some(Scaling {
+        tc: Estimate { temperature: central[0], error: fitted(0).error },
+        gamma_nu: fitted(1),
+        beta_nu: fitted(2),
+        inverse_nu: fitted(3),
+        sizes: sizes.iter().enumerate().map(|(i, s)| AtCritical {
+            length: s.length,
+            num_sites: s.num_sites,
+            susceptibility_max: fitted(4 + 4 * i),
+            magnetization: fitted(5 + 4 * i),
+            binder: fitted(6 + 4 * i),
+            binder_slope: fitted(7 + 4 * i),
+        }).collect(),
+        collapse: best_collapse(&lengths, &susceptibilities, [central[0], central[3], central[1]]),
+    })
+}
+
+/// `run` with Tc, the fitted exponents and the collapse as parameters
+pub fn with_results(run: Run, scaling: &Scaling) -> Run {
+    run.with_parameter("tc", scaling.tc.temperature).with_parameter("tc_error", scaling.tc.error)
+        .with_parameter("gamma_nu", scaling.gamma_nu.value).with_parameter("gamma_nu_error", scaling.gamma_nu.error)
+        .with_parameter("beta_nu", scaling.beta_nu.value).with_parameter("beta_nu_error", scaling.beta_nu.error)
+        .with_parameter("inverse_nu", scaling.inverse_nu.value).with_parameter("inverse_nu_error", scaling.inverse_nu.error)
+        .with_parameter("collapse_tc", scaling.collapse.tc)
+        .with_parameter("collapse_inverse_nu", scaling.collapse.inverse_nu)
+        .with_parameter("collapse_gamma_nu", scaling.collapse.gamma_nu)
+        .with_parameter("collapse_quality", scaling.collapse.quality)
+}
+
+/// Writes the observables of each size at Tc to `{output_dir}/{filename}.csv`, and `run` with the
+/// results to `{output_dir}/{filename}.json`
+pub fn save(scaling: &Scaling, output_dir: &str, filename: &str, run: Run) -> Result<(), LoadError> {
+    let rows = scaling.sizes.iter().map(|s| format!("{},{},{},{},{},{},{},{},{},{}", s.length, s.num_sites,
+        s.susceptibility_max.value, s.susceptibility_max.error,
+        s.magnetization.value, s.magnetization.error,
+        s.binder.value, s.binder.error,
+        s.binder_slope.value, s.binder_slope.error));
+    let run = with_results(run, scaling).with_columns(&COLUMNS);
+    write_table(output_dir, filename, &COLUMNS.join(","), rows, &run)
+}
+
+/// Sweeps the lattice at each of `--sizes`, reusing finished sweeps with `--resume`, and fits Tc
+/// and the critical exponents
+pub fn finite_size<L: Sampler>(options: &Options) -> Result<(), RunError> {
+    let sizes = jobs::run(options.sizes.clone(), options.threads, |index, entry| -> Result<_, RunError> {
+        let task = Options {
+            seed: options.seed.map(|seed| seed.wrapping_add(index as u64)),
+            name: None,
+            ..options.at_size(&entry).map_err(RunError::Options)?
+        };
+        let (geometry, run) = build_geometry(&task, task.eta)?;
+        let name = output_name(&task, &run);
+        let dimension = match geometry.dimension() { 0 => 2, d => d };
+        let length = (geometry.num_sites() as f32).powf(1. / dimension as f32);
+        let data = if finished(&task, &name) {
+            Data::load(&task.output_dir, &name)?
+        } else {
+            log::info(&format!("{} sites from {}", geometry.num_sites(), geometry.provenance));
+            let mut lattice = L::from_geometry(&geometry);
+            sweep(&mut lattice, &task, Command::Sweep, &name, run)?
+        };
+        Ok((length, geometry.num_sites(), data))
+    }).into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let name = options.name.clone().unwrap_or_else(|| format!("{}-{}-scaling", options.lattice, L::model()));
+    let sizes = sizes.iter().map(|(length, num_sites, data)| Size { length: *length, num_sites: *num_sites, data })
+        .collect::<Vec<_>>();
+    let Some(result) = fit(&sizes) else {
+        log::warn("the Binder cumulants of successive sizes do not cross within the temperature range");
+        return Ok(());
+    };
+    log::info(&format!("Tc = {} +/- {}", result.tc.temperature, result.tc.error));
+    log::info(&format!("gamma/nu = {} +/- {}", result.gamma_nu.value, result.gamma_nu.error));
+    log::info(&format!("beta/nu = {} +/- {}", result.beta_nu.value, result.beta_nu.error));
+    log::info(&format!("1/nu = {} +/- {}", result.inverse_nu.value, result.inverse_nu.error));
+    let collapse = &result.collapse;
+    log::info(&format!("best collapse at Tc = {}, 1/nu = {}, gamma/nu = {}, quality {}",
+        collapse.tc, collapse.inverse_nu, collapse.gamma_nu, collapse.quality));
+    options.save(Command::Scaling, &name)?;
+    let mut run = Run::new(&options.lattice, &L::model(), options.trials);
+    if let Some(seed) = options.seed {
+        run = run.with_seed(seed);
+    }
+    save(&result, &options.output_dir, &name, run)?;
+    Ok(())
+}
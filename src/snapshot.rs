@@ -0,0 +1,186 @@
+//! Spin configurations as `.npy` arrays. The `npy` crate only handles flat arrays, so the header
+//! is written and read here to keep the shape.
+
+use std::{fs::{File, OpenOptions}, io::{BufWriter, Read, Seek, SeekFrom, Write}};
+
+use crate::validate::LoadError;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// When `run` writes snapshots of the lattice, to `{prefix}-beta{beta}-{measurement}.npy`
+pub struct Snapshots {
+    pub prefix: String,
+    /// Betas to snapshot. Every beta if empty.
+    pub betas: Vec<f32>,
+    /// Number of measurements between snapshots. If `None`, only the final state is written.
+    pub interval: Option<usize>,
+}
+
+impl Snapshots {
+    pub fn new(prefix: &str) -> Self {
+        Self { prefix: prefix.to_owned(), betas: Vec::new(), interval: None }
+    }
+
+    pub fn at_betas(mut self, betas: &[f32]) -> Self {
+        self.betas = betas.to_vec();
+        self
+    }
+
+    pub fn every(mut self, interval: usize) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Whether to snapshot after `measurement` measurements at `beta`. `last` marks the end of the run.
+    pub fn wants(&self, beta: f32, measurement: usize, last: bool) -> bool {
+        let beta_matches = self.betas.is_empty()
+            || self.betas.iter().any(|b| (b - beta).abs() <= 1e-6 * beta.abs());
+        beta_matches && (last || self.interval.is_some_and(|i| i > 0 && measurement.is_multiple_of(i)))
+    }
+
+    pub fn filename(&self, beta: f32, measurement: usize) -> String {
+        format!("{}-beta{:.6}-{}", self.prefix, beta, measurement)
+    }
+}
+
+/// Everything before the data, padded with spaces to a multiple of 64 bytes and at least `min_len`
+fn header(descr: &str, shape: &[usize], min_len: usize) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({},)", n),
+        _ => format!("({})", shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut dict = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    let unpadded = MAGIC.len() + 4 + dict.len() + 1;
+    let len = unpadded.next_multiple_of(64).max(min_len);
+    dict.push_str(&" ".repeat(len - unpadded));
+    dict.push('\n');
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&[1, 0]);
+    out.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out
+}
+
+/// `LoadError::Io` for a failure to read or write `filename`
+fn io_error(filename: &str) -> impl Fn(std::io::Error) -> LoadError + '_ {
+    move |source| LoadError::Io { file: filename.to_owned(), source }
+}
+
+fn write(filename: &str, descr: &str, shape: &[usize], bytes: &[u8]) -> Result<(), LoadError> {
+    let mut f = File::create(filename).map_err(io_error(filename))?;
+    f.write_all(&header(descr, shape, 0)).and_then(|_| f.write_all(bytes)).map_err(io_error(filename))
+}
+
+/// Length of the header of a `TableWriter` file, enough for any number of rows
+const TABLE_HEADER_LEN: usize = 128;
+
+/// A two-dimensional `f32` `.npy` file written one row at a time. The header is brought up to
+/// date on every `flush`, so the file can be read even while it is being written.
+pub struct TableWriter {
+    filename: String,
+    file: BufWriter<File>,
+    columns: usize,
+    rows: usize,
+}
+
+impl TableWriter {
+    pub fn create(filename: &str, columns: usize) -> Result<Self, LoadError> {
+        let file = BufWriter::new(File::create(filename).map_err(io_error(filename))?);
+        let mut writer = Self { filename: filename.to_owned(), file, columns, rows: 0 };
+        writer.flush()?;
+        Ok(writer)
+    }
+
+    /// Reopen a file written by `create`, keeping only its first `rows` rows. Used to continue a
+    /// run from a checkpoint taken after `rows` rows were flushed.
+    pub fn resume(filename: &str, columns: usize, rows: usize) -> Result<Self, LoadError> {
+        let file = OpenOptions::new().read(true).write(true).open(filename).map_err(io_error(filename))?;
+        let len = (TABLE_HEADER_LEN + 4 * columns * rows) as u64;
+        if file.metadata().map_err(io_error(filename))?.len() < len {
+            return Err(LoadError::Parse { file: filename.to_owned(), line: None, message: format!("has fewer than {} rows", rows) });
+        }
+        file.set_len(len).map_err(io_error(filename))?;
+        let mut file = BufWriter::new(file);
+        file.seek(SeekFrom::End(0)).map_err(io_error(filename))?;
+        Ok(Self { filename: filename.to_owned(), file, columns, rows })
+    }
+
+    pub fn push(&mut self, row: &[f32]) -> Result<(), LoadError> {
+        assert_eq!(row.len(), self.columns, "Every row needs all its columns");
+        for x in row {
+            self.file.write_all(&x.to_le_bytes()).map_err(io_error(&self.filename))?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Write out every row so far, with a header that counts them
+    pub fn flush(&mut self) -> Result<(), LoadError> {
+        let header = header("<f4", &[self.rows, self.columns], TABLE_HEADER_LEN);
+        self.file.seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&header))
+            .and_then(|_| self.file.seek(SeekFrom::End(0)))
+            .and_then(|_| self.file.flush())
+            .map_err(io_error(&self.filename))
+    }
+}
+
+/// Shape and raw little-endian data of a C-ordered `.npy` file of type `descr`
+fn read(filename: &str, descr: &str) -> Result<(Vec<usize>, Vec<u8>), LoadError> {
+    let mut buf = vec![];
+    File::open(filename).and_then(|mut f| f.read_to_end(&mut buf)).map_err(io_error(filename))?;
+    let parse_error = |message: &str| LoadError::Parse { file: filename.to_owned(), line: None, message: message.to_owned() };
+
+    if buf.len() < 10 || &buf[..6] != MAGIC {
+        return Err(parse_error("not a .npy file"));
+    }
+    let (header_start, header_len) = match buf[6] {
+        1 => (10, u16::from_le_bytes([buf[8], buf[9]]) as usize),
+        2 | 3 if buf.len() >= 12 => (12, u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]) as usize),
+        _ => return Err(parse_error("unsupported .npy version")),
+    };
+    let header = buf.get(header_start..header_start + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| parse_error("truncated header"))?;
+
+    let value = |key: &str| header.split(&format!("'{}':", key)).nth(1).map(|rest| rest.trim_start());
+    if !value("descr").is_some_and(|v| v.starts_with(&format!("'{}'", descr))) {
+        return Err(parse_error(&format!("expected an array of type {}", descr)));
+    }
+    if !value("fortran_order").is_some_and(|v| v.starts_with("False")) {
+        return Err(parse_error("expected a C-ordered array"));
+    }
+    let shape = value("shape")
+        .and_then(|v| v.strip_prefix('('))
+        .and_then(|v| v.split(')').next())
+        .ok_or_else(|| parse_error("missing shape"))?
+        .split(',').map(|w| w.trim()).filter(|w| !w.is_empty())
+        .map(|w| w.parse::<usize>()).collect::<Result<Vec<_>, _>>()
+        .map_err(|_| parse_error("could not parse shape"))?;
+
+    let data = buf.split_off(header_start + header_len);
+    let item_size = descr[2..].parse::<usize>().unwrap();
+    if data.len() != shape.iter().product::<usize>() * item_size {
+        return Err(parse_error(&format!("data does not match shape {:?}", shape)));
+    }
+    Ok((shape, data))
+}
+
+pub fn write_f32(filename: &str, shape: &[usize], data: &[f32]) -> Result<(), LoadError> {
+    write(filename, "<f4", shape, &data.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>())
+}
+
+pub fn write_i64(filename: &str, shape: &[usize], data: &[i64]) -> Result<(), LoadError> {
+    write(filename, "<i8", shape, &data.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<_>>())
+}
+
+pub fn read_f32(filename: &str) -> Result<(Vec<usize>, Vec<f32>), LoadError> {
+    let (shape, data) = read(filename, "<f4")?;
+    Ok((shape, data.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect()))
+}
+
+pub fn read_i64(filename: &str) -> Result<(Vec<usize>, Vec<i64>), LoadError> {
+    let (shape, data) = read(filename, "<i8")?;
+    Ok((shape, data.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect()))
+}
@@ -4,8 +4,6 @@ use std::fmt::Debug;
 use rand::prelude::*;
 use rand_distr::StandardNormal;
 
-const MAX_NEIGHBORS: usize = 12;
-
 #[derive(Debug)]
 pub struct Ising {
     data: i32,
@@ -56,15 +54,30 @@ impl Spin for Ising {
     fn norm(&self) -> f32 {
         (self.data as f32).abs()
     }
-    fn random_vec() -> Self::V {
+    fn coupling(&self, other: &Self) -> f32 {
+        (self.data * other.data) as f32
+    }
+    fn random_vec(_rng: &mut impl Rng) -> Self::V {
         0
     }
-    fn start() -> Self {
-        Self { data: if random::<bool>() {1} else {-1} }
+    fn start(rng: &mut impl Rng) -> Self {
+        Self { data: if rng.gen::<bool>() {1} else {-1} }
     }
     fn zero() -> Self {
         Self { data: 0 }
     }
+    fn up() -> Self {
+        Self { data: 1 }
+    }
+    fn num_components() -> usize {
+        1
+    }
+    fn components(&self) -> Vec<f32> {
+        vec![self.data as f32]
+    }
+    fn from_components(components: &[f32]) -> Self {
+        Self { data: if components[0] < 0. {-1} else {1} }
+    }
     fn name() -> String {
         "ising".to_owned()
     }
@@ -86,19 +99,34 @@ impl Spin for XY {
     fn norm(&self) -> f32 {
         (self.x * self.x + self.y * self.y).sqrt()
     }
-    fn random_vec() -> Self::V {
-        let x = 0.1 * thread_rng().sample::<f32,_>(StandardNormal);
-        let y = 0.1 * thread_rng().sample::<f32,_>(StandardNormal);
+    fn coupling(&self, other: &Self) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+    fn random_vec(rng: &mut impl Rng) -> Self::V {
+        let x = 0.1 * rng.sample::<f32,_>(StandardNormal);
+        let y = 0.1 * rng.sample::<f32,_>(StandardNormal);
         let norm = 1./(x*x + y*y).sqrt();
         (x * norm, y * norm)
     }
-    fn start() -> Self {
-        let (x, y) = Self::random_vec();
+    fn start(rng: &mut impl Rng) -> Self {
+        let (x, y) = Self::random_vec(rng);
         Self { x, y }
     }
     fn zero() -> Self {
         Self { x: 0., y: 0. }
     }
+    fn up() -> Self {
+        Self { x: 1., y: 0. }
+    }
+    fn num_components() -> usize {
+        2
+    }
+    fn components(&self) -> Vec<f32> {
+        vec![self.x, self.y]
+    }
+    fn from_components(components: &[f32]) -> Self {
+        Self { x: components[0], y: components[1] }
+    }
     fn name() -> String {
         "xy".to_owned()
     }
@@ -122,73 +150,37 @@ impl Spin for Heisenberg {
     fn norm(&self) -> f32 {
         (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
     }
-    fn random_vec() -> Self::V {
-        let x = 0.1 * thread_rng().sample::<f32,_>(StandardNormal);
-        let y = 0.1 * thread_rng().sample::<f32,_>(StandardNormal);
-        let z = 0.1 * thread_rng().sample::<f32,_>(StandardNormal);
+    fn coupling(&self, other: &Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+    fn random_vec(rng: &mut impl Rng) -> Self::V {
+        let x = 0.1 * rng.sample::<f32,_>(StandardNormal);
+        let y = 0.1 * rng.sample::<f32,_>(StandardNormal);
+        let z = 0.1 * rng.sample::<f32,_>(StandardNormal);
         let norm = 1./(x*x + y*y + z*z).sqrt();
         (x * norm, y * norm, z * norm)
     }
-    fn start() -> Self {
-        let (x, y, z) = Self::random_vec();
+    fn start(rng: &mut impl Rng) -> Self {
+        let (x, y, z) = Self::random_vec(rng);
         Self { x, y, z }
     }
     fn zero() -> Self {
         Self { x: 0., y: 0., z: 0. }
     }
-    fn name() -> String {
-        "heisenberg".to_owned()
+    fn up() -> Self {
+        Self { x: 1., y: 0., z: 0. }
     }
-}
-
-#[derive(Debug)]
-pub struct SmallVec<T> {
-    data: [Option<T>; MAX_NEIGHBORS]
-}
-
-pub struct SmallVecIter<'a, T> {
-    origin: &'a SmallVec<T>,
-    index: usize,
-}
-
-impl<'a, T> Iterator for SmallVecIter<'a, T> {
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        let val = self.origin.data[self.index].as_ref();
-        self.index += 1;
-        val
-    }
-}
-
-impl<T: Copy> SmallVec<T> {
-    pub fn new() -> Self {
-        Self {
-            data: [None; MAX_NEIGHBORS],
-        }
+    fn num_components() -> usize {
+        3
     }
-
-    pub fn push(&mut self, val: T) {
-        for i in 0..MAX_NEIGHBORS {
-            if let None = self.data[i] {
-                self.data[i] = Some(val);
-                return;
-            }
-        }
-        panic!("Could not push because the list was full")
+    fn components(&self) -> Vec<f32> {
+        vec![self.x, self.y, self.z]
     }
-
-    pub fn iter<'a>(&'a self) -> SmallVecIter<'a, T> {
-        SmallVecIter {
-            origin: &self,
-            index: 0,
-        }
+    fn from_components(components: &[f32]) -> Self {
+        Self { x: components[0], y: components[1], z: components[2] }
     }
-
-    pub fn iter_mut<'a>(&'a mut self) -> SmallVecIter<'a, T> {
-        SmallVecIter {
-            origin: self,
-            index: 0,
-        }
+    fn name() -> String {
+        "heisenberg".to_owned()
     }
 }
 
@@ -200,13 +192,23 @@ pub trait Spin: for <'a> AddAssign<&'a Self> + Sized + Debug {
     /// Dot two spin values against the other
     fn dot(&mut self, vec: &Self::V) -> f32;
     /// Generate a random seed vector to flip
-    fn random_vec() -> Self::V;
+    fn random_vec(rng: &mut impl Rng) -> Self::V;
     /// Get a zero-valued spin for the sake of averaging
     fn zero() -> Self;
     /// Generate a random spin value to start
-    fn start() -> Self;
+    fn start(rng: &mut impl Rng) -> Self;
+    /// Get the spin pointing along the first axis, for ordered starts
+    fn up() -> Self;
+    /// Number of components of the spin vector
+    fn num_components() -> usize;
+    /// Get the components of the spin vector, for snapshots
+    fn components(&self) -> Vec<f32>;
+    /// Build a spin from its components, for loading snapshots
+    fn from_components(components: &[f32]) -> Self;
     /// Get the norm for the sake of averaging
     fn norm(&self) -> f32;
+    /// Dot this spin against a neighboring spin, for the energy
+    fn coupling(&self, other: &Self) -> f32;
     /// Get the name of this spin model
     fn name() -> String;
 }
\ No newline at end of file
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use crate::geometry::{Geometry, Boundary};
+
+/// Planar similarity (rotation, scaling and optional reflection) followed by a shift
+#[derive(Clone, Copy, Debug)]
+pub struct Transform {
+    linear: [[f32; 2]; 2],
+    shift: [f32; 2],
+}
+
+/// A polygonal prototile and its substitution rule. Edge `i` runs from vertex `i` to vertex `i+1`
+/// and has type `edge_types[i]`. Each child is a prototile index placed by a transform in the
+/// parent's coordinates, so the children exactly cover the parent.
+pub struct Prototile {
+    pub vertices: Vec<[f32; 2]>,
+    pub edge_types: Vec<usize>,
+    pub children: Vec<(usize, Transform)>,
+}
+
+/// A substitution tiling. Each step shrinks the children of every tile by `1 / inflation`.
+pub struct Substitution {
+    pub prototiles: Vec<Prototile>,
+    pub inflation: f32,
+}
+
+/// Vertex graph of a substitution tiling. Edges are `(site, site, edge type)` and listed once.
+pub struct Tiling {
+    pub positions: Vec<[f32; 2]>,
+    pub edges: Vec<(usize, usize, usize)>,
+}
+
+impl Transform {
+    /// Scale by `scale`, then rotate by `angle`, then shift. If `reflect` is set, the y axis is
+    /// flipped before anything else.
+    pub fn new(scale: f32, angle: f32, reflect: bool, shift: [f32; 2]) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let flip = if reflect { -1. } else { 1. };
+        Self {
+            linear: [[scale * cos, -scale * sin * flip], [scale * sin, scale * cos * flip]],
+            shift,
+        }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1., 0., false, [0., 0.])
+    }
+
+    pub fn apply(&self, p: [f32; 2]) -> [f32; 2] {
+        [
+            self.linear[0][0] * p[0] + self.linear[0][1] * p[1] + self.shift[0],
+            self.linear[1][0] * p[0] + self.linear[1][1] * p[1] + self.shift[1],
+        ]
+    }
+
+    /// The transform that applies `inner` first, then `self`
+    pub fn compose(&self, inner: &Transform) -> Self {
+        let mut linear = [[0.; 2]; 2];
+        for (i, row) in linear.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.linear[i][0] * inner.linear[0][j] + self.linear[i][1] * inner.linear[1][j];
+            }
+        }
+        let shifted = self.apply(inner.shift);
+        Self { linear, shift: shifted }
+    }
+}
+
+fn distance2(a: [f32; 2], b: [f32; 2]) -> f32 {
+    (a[0] - b[0]) * (a[0] - b[0]) + (a[1] - b[1]) * (a[1] - b[1])
+}
+
+impl Substitution {
+    /// The chair tiling of L-shaped trominoes, with inflation factor 2
+    pub fn chair() -> Self {
+        let quarter_turn = std::f32::consts::FRAC_PI_2;
+        Self {
+            prototiles: vec![Prototile {
+                vertices: vec![[0., 0.], [2., 0.], [2., 1.], [1., 1.], [1., 2.], [0., 2.]],
+                edge_types: vec![0; 6],
+                children: vec![
+                    (0, Transform::new(0.5, 0., false, [0., 0.])),
+                    (0, Transform::new(0.5, 0., false, [0.5, 0.5])),
+                    (0, Transform::new(0.5, quarter_turn, false, [2., 0.])),
+                    (0, Transform::new(0.5, -quarter_turn, false, [0., 2.])),
+                ],
+            }],
+            inflation: 2.,
+        }
+    }
+
+    /// Substitute the `seed` tiles `level` times and return the vertex graph. Lengths are rescaled
+    /// by the inflation factor so that edges keep the size of the prototiles. Vertices closer than
+    /// `tolerance` are merged, and edges are split wherever a vertex lies on them.
+    pub fn generate(&self, seed: &[(usize, Transform)], level: usize, tolerance: f32) -> Tiling {
+        let mut tiles = seed.to_vec();
+        for _ in 0..level {
+            let mut next = Vec::new();
+            for (prototile, transform) in &tiles {
+                for (child, child_transform) in &self.prototiles[*prototile].children {
+                    next.push((*child, transform.compose(child_transform)));
+                }
+            }
+            tiles = next;
+        }
+        let rescale = Transform::new(self.inflation.powi(level as i32), 0., false, [0., 0.]);
+
+        // Cells are as large as the longest edge, so that every edge lies within the 3x3 block
+        // around its first vertex.
+        let mut cell_size = tolerance;
+        for prototile in &self.prototiles {
+            let n = prototile.vertices.len();
+            for i in 0..n {
+                cell_size = cell_size.max(distance2(prototile.vertices[i], prototile.vertices[(i + 1) % n]).sqrt());
+            }
+        }
+        let cell = |p: [f32; 2]| { ((p[0] / cell_size).floor() as i64, (p[1] / cell_size).floor() as i64) };
+        let block = |p: [f32; 2]| {
+            let (x, y) = cell(p);
+            (0..9).map(move |k| (x + k % 3 - 1, y + k / 3 - 1))
+        };
+
+        // 1. Merge vertices
+        let mut positions: Vec<[f32; 2]> = Vec::new();
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        let mut tile_vertices = Vec::with_capacity(tiles.len());
+        for (prototile, transform) in &tiles {
+            let transform = rescale.compose(transform);
+            let mut indices = Vec::new();
+            for vertex in &self.prototiles[*prototile].vertices {
+                let p = transform.apply(*vertex);
+                let found = block(p).flat_map(|key| cells.get(&key).into_iter().flatten())
+                    .find(|i| distance2(positions[**i], p) < tolerance * tolerance).copied();
+                indices.push(match found {
+                    Some(i) => i,
+                    None => {
+                        cells.entry(cell(p)).or_default().push(positions.len());
+                        positions.push(p);
+                        positions.len() - 1
+                    }
+                });
+            }
+            tile_vertices.push(indices);
+        }
+
+        // 2. Split edges at any vertex lying on them, and remove duplicates
+        let mut edge_map = HashMap::new();
+        for ((prototile, _), indices) in tiles.iter().zip(&tile_vertices) {
+            let n = indices.len();
+            for i in 0..n {
+                let (a, b) = (indices[i], indices[(i + 1) % n]);
+                let edge_type = self.prototiles[*prototile].edge_types[i];
+                let (pa, pb) = (positions[a], positions[b]);
+                let length2 = distance2(pa, pb);
+                let mut stops = block(pa).flat_map(|key| cells.get(&key).into_iter().flatten())
+                    .filter_map(|c| {
+                        let pc = positions[*c];
+                        let t = ((pc[0] - pa[0]) * (pb[0] - pa[0]) + (pc[1] - pa[1]) * (pb[1] - pa[1])) / length2;
+                        let foot = [pa[0] + t * (pb[0] - pa[0]), pa[1] + t * (pb[1] - pa[1])];
+                        (*c != a && *c != b && t > 0. && t < 1. && distance2(foot, pc) < tolerance * tolerance)
+                            .then_some((t, *c))
+                    }).collect::<Vec<_>>();
+                stops.sort_by(|x, y| x.0.total_cmp(&y.0));
+                let mut last = a;
+                for next in stops.iter().map(|(_, c)| *c).chain([b]) {
+                    edge_map.entry((last.min(next), last.max(next))).or_insert(edge_type);
+                    last = next;
+                }
+            }
+        }
+        let mut edges = edge_map.into_iter().map(|((a, b), t)| (a, b, t)).collect::<Vec<_>>();
+        edges.sort();
+
+        Tiling { positions, edges }
+    }
+}
+
+impl Tiling {
+    /// Neighbor function for `Lattice::new`, where an edge of type `t` has coupling `couplings[t]`
+    pub fn neighbor_fn(&self, couplings: &[f32]) -> (usize, impl Fn(usize) -> Vec<(usize, f32)>) {
+        let mut adjacency = vec![Vec::new(); self.positions.len()];
+        for (a, b, edge_type) in &self.edges {
+            adjacency[*a].push((*b, couplings[*edge_type]));
+            adjacency[*b].push((*a, couplings[*edge_type]));
+        }
+        (
+            self.positions.len(),
+            move |site: usize| { adjacency[site].clone() }
+        )
+    }
+
+    /// Geometry file contents, where edges of type `t` have default coupling `couplings[t]`
+    pub fn geometry(&self, couplings: &[f32], provenance: &str) -> Geometry {
+        Geometry::new(
+            self.positions.len(),
+            self.edges.clone(),
+            couplings.iter().enumerate().map(|(t, j)| (format!("type{}", t), *j)).collect(),
+            Boundary::Open,
+            provenance,
+        ).with_positions(self.positions.iter().map(|p| p.to_vec()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chair_has_four_times_as_many_tiles_each_level() {
+        // The patch is a disk, so by Euler's formula it has `E - V + 1` tiles
+        for level in 0..5 {
+            let tiling = Substitution::chair().generate(&[(0, Transform::identity())], level, 1e-3);
+            let tiles = tiling.edges.len() + 1 - tiling.positions.len();
+            assert_eq!(tiles, 4usize.pow(level as u32), "level {}", level);
+        }
+    }
+
+    #[test]
+    fn chair_edges_keep_the_size_of_the_prototile() {
+        let tiling = Substitution::chair().generate(&[(0, Transform::identity())], 4, 1e-3);
+        for (a, b, _) in &tiling.edges {
+            let length = distance2(tiling.positions[*a], tiling.positions[*b]).sqrt();
+            assert!((length - 1.).abs() < 1e-4 || (length - 2.).abs() < 1e-4, "edge of length {}", length);
+        }
+        let (_, func) = tiling.neighbor_fn(&[1.]);
+        assert!((0..tiling.positions.len()).all(|site| (2..=4).contains(&func(site).len())));
+    }
+
+    #[test]
+    fn composed_transforms_apply_in_turn() {
+        let outer = Transform::new(2., 0.3, true, [1., -1.]);
+        let inner = Transform::new(0.5, -1.1, false, [0.25, 2.]);
+        let p = [0.7, -0.2];
+        let (composed, in_turn) = (outer.compose(&inner).apply(p), outer.apply(inner.apply(p)));
+        assert!(distance2(composed, in_turn) < 1e-10);
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Display;
+use std::ops::RangeInclusive;
+
+/// Error from reading or writing a file
+#[derive(Debug)]
+pub enum LoadError {
+    Io { file: String, source: std::io::Error },
+    /// The file could not be parsed. `line` is the line of a text file, if known.
+    Parse { file: String, line: Option<usize>, message: String },
+    /// The file parsed, but describes a broken graph
+    Invalid { file: String, problems: Vec<Problem> },
+}
+
+/// A defect in the graph given by a neighbor function
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// A neighbor index is past the end of the lattice
+    OutOfRange { site: usize, neighbor: usize },
+    SelfLoop { site: usize },
+    DuplicateBond { site: usize, neighbor: usize },
+    /// `site` lists `neighbor`, but not the other way around or with a different coupling
+    Asymmetric { site: usize, neighbor: usize },
+    DegreeOutOfRange { site: usize, degree: usize },
+    /// The graph falls apart into `components` pieces, the largest having `largest` sites
+    Disconnected { components: usize, largest: usize },
+}
+
+/// What a valid lattice looks like, beyond having symmetric, simple bonds
+pub struct Expect {
+    pub degrees: RangeInclusive<usize>,
+    pub connected: bool,
+}
+
+impl Expect {
+    /// Connected, with no isolated sites
+    pub fn connected() -> Self {
+        Self { degrees: 1..=usize::MAX, connected: true }
+    }
+
+    /// Any simple graph, including isolated sites and several components
+    pub fn any() -> Self {
+        Self { degrees: 0..=usize::MAX, connected: false }
+    }
+}
+
+const MAX_PRINTED_PROBLEMS: usize = 10;
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io { file, source } => write!(f, "{}: {}", file, source),
+            LoadError::Parse { file, line: Some(line), message } => write!(f, "{}:{}: {}", file, line, message),
+            LoadError::Parse { file, line: None, message } => write!(f, "{}: {}", file, message),
+            LoadError::Invalid { file, problems } => {
+                write!(f, "{}: {} problems", file, problems.len())?;
+                for problem in problems.iter().take(MAX_PRINTED_PROBLEMS) {
+                    write!(f, "\n    {}", problem)?;
+                }
+                if problems.len() > MAX_PRINTED_PROBLEMS {
+                    write!(f, "\n    ...")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::OutOfRange { site, neighbor } => write!(f, "site {} has neighbor {} past the end of the lattice", site, neighbor),
+            Problem::SelfLoop { site } => write!(f, "site {} is bonded to itself", site),
+            Problem::DuplicateBond { site, neighbor } => write!(f, "sites {} and {} are bonded more than once", site, neighbor),
+            Problem::Asymmetric { site, neighbor } => write!(f, "site {} lists {} as a neighbor, but not the other way around", site, neighbor),
+            Problem::DegreeOutOfRange { site, degree } => write!(f, "site {} has {} neighbors", site, degree),
+            Problem::Disconnected { components, largest } => write!(f, "{} disconnected components, the largest with {} sites", components, largest),
+        }
+    }
+}
+
+/// Checks the graph of a neighbor function and returns every problem found
+pub fn validate(num_sites: usize, neighbor_func: &impl Fn(usize) -> Vec<(usize, f32)>, expect: &Expect) -> Vec<Problem> {
+    let neighbors = (0..num_sites).map(neighbor_func).collect::<Vec<_>>();
+    let mut problems = Vec::new();
+    for (site, list) in neighbors.iter().enumerate() {
+        if !expect.degrees.contains(&list.len()) {
+            problems.push(Problem::DegreeOutOfRange { site, degree: list.len() });
+        }
+        for (i, (neighbor, strength)) in list.iter().enumerate() {
+            if *neighbor >= num_sites {
+                problems.push(Problem::OutOfRange { site, neighbor: *neighbor });
+                continue;
+            }
+            if list[..i].iter().any(|(other, _)| other == neighbor) {
+                if site < *neighbor {
+                    problems.push(Problem::DuplicateBond { site, neighbor: *neighbor });
+                }
+                continue;
+            }
+            if *neighbor == site {
+                problems.push(Problem::SelfLoop { site });
+                continue;
+            }
+            if !neighbors[*neighbor].iter().any(|(other, s)| *other == site && s == strength) {
+                problems.push(Problem::Asymmetric { site, neighbor: *neighbor });
+            }
+        }
+    }
+
+    if expect.connected && num_sites > 0 {
+        let mut component = vec![usize::MAX; num_sites];
+        let mut sizes = Vec::new();
+        for start in 0..num_sites {
+            if component[start] != usize::MAX { continue; }
+            let label = sizes.len();
+            let mut stack = vec![start];
+            component[start] = label;
+            let mut size = 0;
+            while let Some(site) = stack.pop() {
+                size += 1;
+                for (neighbor, _) in &neighbors[site] {
+                    if *neighbor < num_sites && component[*neighbor] == usize::MAX {
+                        component[*neighbor] = label;
+                        stack.push(*neighbor);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        if sizes.len() > 1 {
+            problems.push(Problem::Disconnected { components: sizes.len(), largest: *sizes.iter().max().unwrap() });
+        }
+    }
+    problems
+}